@@ -1,11 +1,521 @@
-#![feature(is_sorted)]
-
 use std::{
-    cmp::Ordering,
-    collections::{HashMap, HashSet},
-    fs,
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    env, fs, iter,
 };
 
+trait PageOrder {
+    fn is_ordered(&self, update: &[isize]) -> bool;
+    fn reorder(&self, update: &mut [isize]);
+}
+
+impl PageOrder for HashSet<(isize, isize)> {
+    fn is_ordered(&self, update: &[isize]) -> bool {
+        is_ordered(update, self)
+    }
+    fn reorder(&self, update: &mut [isize]) {
+        update.sort_by(|&a, &b| compare_pages(a, b, self));
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct RuleGraph {
+    successors: HashMap<isize, HashSet<isize>>,
+}
+
+impl RuleGraph {
+    fn new(pairs: &[(isize, isize)]) -> Self {
+        let mut graph = Self::default();
+        for (l, r) in pairs {
+            graph.add_rule(*l, *r);
+        }
+        graph
+    }
+    fn add_rule(&mut self, l: isize, r: isize) -> bool {
+        self.successors.entry(l).or_default().insert(r)
+    }
+    fn remove_rule(&mut self, l: isize, r: isize) -> bool {
+        self.successors.get_mut(&l).is_some_and(|s| s.remove(&r))
+    }
+    // Follows chains of rules through pages outside of any particular update.
+    fn is_before(&self, a: isize, b: isize) -> bool {
+        let mut seen = HashSet::from([a]);
+        let mut queue = VecDeque::from([a]);
+        while let Some(p) = queue.pop_front() {
+            for q in self.successors.get(&p).into_iter().flatten() {
+                if *q == b {
+                    return true;
+                }
+                if seen.insert(*q) {
+                    queue.push_back(*q);
+                }
+            }
+        }
+        false
+    }
+    fn restrict(&self, update: &[isize]) -> HashMap<isize, Vec<isize>> {
+        let pages: HashSet<isize> = update.iter().copied().collect();
+        update
+            .iter()
+            .map(|p| {
+                let successors = self
+                    .successors
+                    .get(p)
+                    .map(|s| s.iter().filter(|q| pages.contains(q)).copied().collect())
+                    .unwrap_or_default();
+                (*p, successors)
+            })
+            .collect()
+    }
+    // Pages repeated in the update appear once in the result.
+    fn topo_sort(&self, update: &[isize]) -> Option<Vec<isize>> {
+        let mut seen = HashSet::new();
+        let update: Vec<isize> = update.iter().copied().filter(|p| seen.insert(*p)).collect();
+        let restricted = self.restrict(&update);
+        let mut in_degree: HashMap<isize, usize> = update.iter().map(|p| (*p, 0)).collect();
+        for q in restricted.values().flatten() {
+            *in_degree.get_mut(q).expect("Inconsistent restriction") += 1;
+        }
+        let mut queue: VecDeque<isize> = update
+            .iter()
+            .filter(|p| in_degree[p] == 0)
+            .copied()
+            .collect();
+        let mut sorted = Vec::with_capacity(update.len());
+        while let Some(p) = queue.pop_front() {
+            sorted.push(p);
+            for q in restricted[&p].iter() {
+                let d = in_degree.get_mut(q).expect("Inconsistent restriction");
+                *d -= 1;
+                if *d == 0 {
+                    queue.push_back(*q);
+                }
+            }
+        }
+        (sorted.len() == in_degree.len()).then_some(sorted)
+    }
+}
+
+// The first and last position of each page in an update. The rule p|q holds when every p is
+// before every q, i.e. the last p is before the first q.
+fn spans(update: &[isize]) -> HashMap<isize, (usize, usize)> {
+    let mut spans = HashMap::new();
+    for (j, p) in update.iter().enumerate() {
+        spans.entry(*p).or_insert((j, j)).1 = j;
+    }
+    spans
+}
+
+impl PageOrder for RuleGraph {
+    fn is_ordered(&self, update: &[isize]) -> bool {
+        let spans = spans(update);
+        spans.iter().all(|(p, &(_, last))| {
+            self.successors.get(p).is_none_or(|s| {
+                s.iter()
+                    .all(|q| spans.get(q).is_none_or(|&(first, _)| first > last))
+            })
+        })
+    }
+    // Repeats of a page are kept together where the page sorts.
+    fn reorder(&self, update: &mut [isize]) {
+        let mut counts: HashMap<isize, usize> = HashMap::new();
+        for p in update.iter() {
+            *counts.entry(*p).or_default() += 1;
+        }
+        let sorted: Vec<isize> = self
+            .topo_sort(update)
+            .expect("Rules restricted to update are cyclic")
+            .into_iter()
+            .flat_map(|p| iter::repeat_n(p, counts[&p]))
+            .collect();
+        update.copy_from_slice(&sorted);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Violation {
+    rule: (isize, isize),
+    positions: (usize, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Move {
+    page: isize,
+    from: usize,
+    to: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Diagnostic {
+    update: Vec<isize>,
+    violations: Vec<Violation>,
+    moves: Vec<Move>,
+    sorted: Vec<isize>,
+}
+
+impl RuleGraph {
+    fn has_rule(&self, a: isize, b: isize) -> bool {
+        self.successors.get(&a).is_some_and(|s| s.contains(&b))
+    }
+    fn reachability(&self, update: &[isize]) -> Vec<Vec<bool>> {
+        let n = update.len();
+        let mut reach: Vec<Vec<bool>> = update
+            .iter()
+            .map(|&p| update.iter().map(|&q| self.has_rule(p, q)).collect())
+            .collect();
+        for k in 0..n {
+            let via = reach[k].clone();
+            for row in reach.iter_mut().filter(|row| row[k]) {
+                row.iter_mut().zip(via.iter()).for_each(|(r, v)| *r |= v);
+            }
+        }
+        reach
+    }
+    fn explain(&self, update: &[isize]) -> Option<Diagnostic> {
+        let n = update.len();
+        let reach = self.reachability(update);
+        if (0..n).any(|i| reach[i][i]) {
+            return None;
+        }
+        let mut violations = Vec::new();
+        for (i, &p) in update.iter().enumerate() {
+            for (j, &q) in update.iter().enumerate().skip(i + 1) {
+                if self.has_rule(q, p) {
+                    violations.push(Violation {
+                        rule: (q, p),
+                        positions: (j, i),
+                    });
+                }
+            }
+        }
+        // Pages that stay put must not be forced into the opposite order, not even transitively
+        // via pages that move. Every other page is moved exactly once.
+        let kept = longest_consistent_subsequence(&reach);
+        let mut in_degree = vec![0; n];
+        let mut edges = vec![Vec::new(); n];
+        for (i, row) in reach.iter().enumerate() {
+            for (j, _) in row.iter().enumerate().filter(|(_, r)| **r) {
+                edges[i].push(j);
+                in_degree[j] += 1;
+            }
+        }
+        for w in kept.windows(2) {
+            edges[w[0]].push(w[1]);
+            in_degree[w[1]] += 1;
+        }
+        let mut available: BinaryHeap<Reverse<usize>> =
+            (0..n).filter(|&i| in_degree[i] == 0).map(Reverse).collect();
+        let mut order = Vec::with_capacity(n);
+        while let Some(Reverse(i)) = available.pop() {
+            order.push(i);
+            for &j in edges[i].iter() {
+                in_degree[j] -= 1;
+                if in_degree[j] == 0 {
+                    available.push(Reverse(j));
+                }
+            }
+        }
+        let moves = order
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| !kept.contains(i))
+            .map(|(to, &from)| Move {
+                page: update[from],
+                from,
+                to,
+            })
+            .collect();
+        Some(Diagnostic {
+            update: update.to_vec(),
+            violations,
+            moves,
+            sorted: order.iter().map(|&i| update[i]).collect(),
+        })
+    }
+}
+
+impl RuleGraph {
+    // None when the rules restricted to the update are cyclic, since then there is nothing to
+    // enumerate, and searching for orderings anyway would walk every partial one.
+    fn predecessor_masks(&self, update: &[isize]) -> Result<Option<Vec<u64>>, String> {
+        if update.len() > 64 {
+            return Err(format!("{} pages, at most 64 are supported", update.len()));
+        }
+        if self.topo_sort(update).is_none() {
+            return Ok(None);
+        }
+        Ok(Some(
+            update
+                .iter()
+                .map(|&q| {
+                    update
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, &p)| self.has_rule(p, q))
+                        .fold(0, |mask, (i, _)| mask | 1 << i)
+                })
+                .collect(),
+        ))
+    }
+    // Memoised over the sets of pages already placed, i.e. the down-sets of the restricted
+    // rules, so this is cheap when the rules (nearly) totally order the update. Cyclic rules
+    // admit no ordering at all.
+    fn count_orderings(&self, update: &[isize]) -> Result<u128, String> {
+        fn count(placed: u64, pred: &[u64], memo: &mut HashMap<u64, u128>) -> u128 {
+            if placed.count_ones() as usize == pred.len() {
+                return 1;
+            }
+            if let Some(c) = memo.get(&placed) {
+                return *c;
+            }
+            let c = (0..pred.len())
+                .filter(|&j| placed & 1 << j == 0 && pred[j] & !placed == 0)
+                .map(|j| count(placed | 1 << j, pred, memo))
+                .sum();
+            memo.insert(placed, c);
+            c
+        }
+        Ok(self
+            .predecessor_masks(update)?
+            .map_or(0, |pred| count(0, &pred, &mut HashMap::new())))
+    }
+    fn orderings(&self, update: &[isize], limit: usize) -> Result<Vec<Vec<isize>>, String> {
+        fn extend(
+            placed: u64,
+            pred: &[u64],
+            current: &mut Vec<usize>,
+            found: &mut Vec<Vec<usize>>,
+            limit: usize,
+        ) {
+            if found.len() == limit {
+                return;
+            }
+            if current.len() == pred.len() {
+                found.push(current.clone());
+                return;
+            }
+            for j in 0..pred.len() {
+                if placed & 1 << j == 0 && pred[j] & !placed == 0 {
+                    current.push(j);
+                    extend(placed | 1 << j, pred, current, found, limit);
+                    current.pop();
+                }
+            }
+        }
+        let Some(pred) = self.predecessor_masks(update)? else {
+            return Ok(Vec::new());
+        };
+        let mut found = Vec::new();
+        extend(0, &pred, &mut Vec::new(), &mut found, limit);
+        Ok(found
+            .iter()
+            .map(|o| o.iter().map(|&i| update[i]).collect())
+            .collect())
+    }
+    // A page can take exactly the positions between the number of pages forced before it and
+    // the number of pages forced after it, so the middle is fixed iff one page is comparable
+    // to every other page and has equally many on either side.
+    fn unique_middle(&self, update: &[isize]) -> Option<isize> {
+        let n = update.len();
+        let reach = self.reachability(update);
+        if (0..n).any(|i| reach[i][i]) {
+            return None;
+        }
+        (0..n)
+            .find(|&i| {
+                let before = (0..n).filter(|&j| reach[j][i]).count();
+                let after = reach[i].iter().filter(|&&r| r).count();
+                before == n / 2 && after == n - 1 - n / 2
+            })
+            .map(|i| update[i])
+    }
+}
+
+fn longest_consistent_subsequence(reach: &[Vec<bool>]) -> Vec<usize> {
+    fn extend(j: usize, reach: &[Vec<bool>], chosen: &mut Vec<usize>, best: &mut Vec<usize>) {
+        let n = reach.len();
+        if chosen.len() + (n - j) <= best.len() {
+            return;
+        }
+        if j == n {
+            best.clone_from(chosen);
+            return;
+        }
+        if chosen.iter().all(|&i| !reach[j][i]) {
+            chosen.push(j);
+            extend(j + 1, reach, chosen, best);
+            chosen.pop();
+        }
+        extend(j + 1, reach, chosen, best);
+    }
+    let mut best = Vec::new();
+    extend(0, reach, &mut Vec::new(), &mut best);
+    best
+}
+
+fn join(pages: &[isize]) -> String {
+    pages
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl Diagnostic {
+    fn to_json(&self) -> String {
+        let violations: Vec<String> = self
+            .violations
+            .iter()
+            .map(|v| {
+                format!(
+                    "{{\"rule\":[{},{}],\"positions\":[{},{}]}}",
+                    v.rule.0, v.rule.1, v.positions.0, v.positions.1
+                )
+            })
+            .collect();
+        let moves: Vec<String> = self
+            .moves
+            .iter()
+            .map(|m| {
+                format!(
+                    "{{\"page\":{},\"from\":{},\"to\":{}}}",
+                    m.page, m.from, m.to
+                )
+            })
+            .collect();
+        format!(
+            "{{\"update\":[{}],\"violations\":[{}],\"moves\":[{}],\"sorted\":[{}]}}",
+            join(&self.update),
+            violations.join(","),
+            moves.join(","),
+            join(&self.sorted)
+        )
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}: {} violated rule(s)",
+            join(&self.update),
+            self.violations.len()
+        )?;
+        for v in self.violations.iter() {
+            writeln!(
+                f,
+                "  rule {}|{} broken: {} at position {}, {} at position {}",
+                v.rule.0, v.rule.1, v.rule.1, v.positions.1, v.rule.0, v.positions.0
+            )?;
+        }
+        for m in self.moves.iter() {
+            writeln!(
+                f,
+                "  move {} from position {} to position {}",
+                m.page, m.from, m.to
+            )?;
+        }
+        write!(f, "  fixed: {}", join(&self.sorted))
+    }
+}
+
+// Keeps the number of violated rules per update up to date as rules come and go. Changing the
+// rule l|r can only affect updates containing both l and r, so those are the only ones touched.
+// A rule counts once per update however many occurrences of its pages break it.
+#[derive(Debug, Clone, Default)]
+struct PrintQueue {
+    rules: RuleGraph,
+    updates: Vec<Vec<isize>>,
+    spans: Vec<HashMap<isize, (usize, usize)>>,
+    containing: HashMap<isize, Vec<usize>>,
+    violations: Vec<usize>,
+}
+
+impl PrintQueue {
+    fn new(rules: RuleGraph, updates: Vec<Vec<isize>>) -> Self {
+        let mut queue = Self {
+            rules,
+            ..Default::default()
+        };
+        for u in updates {
+            queue.push_update(u);
+        }
+        queue
+    }
+    fn push_update(&mut self, update: Vec<isize>) -> usize {
+        let id = self.updates.len();
+        let spans = spans(&update);
+        for p in spans.keys() {
+            self.containing.entry(*p).or_default().push(id);
+        }
+        let violations = spans
+            .iter()
+            .map(|(p, &(_, last))| {
+                self.rules.successors.get(p).map_or(0, |s| {
+                    s.iter()
+                        .filter(|q| spans.get(q).is_some_and(|&(first, _)| first < last))
+                        .count()
+                })
+            })
+            .sum();
+        self.updates.push(update);
+        self.spans.push(spans);
+        self.violations.push(violations);
+        id
+    }
+    fn affected(&self, l: isize, r: isize) -> Vec<usize> {
+        self.containing
+            .get(&l)
+            .map(|ids| {
+                ids.iter()
+                    .filter(|&&id| self.spans[id].contains_key(&r))
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    // Both return the ids of the updates whose validity flipped.
+    fn add_rule(&mut self, l: isize, r: isize) -> Vec<usize> {
+        if !self.rules.add_rule(l, r) {
+            return Vec::new();
+        }
+        self.recount(l, r, |v| *v += 1)
+    }
+    fn remove_rule(&mut self, l: isize, r: isize) -> Vec<usize> {
+        if !self.rules.remove_rule(l, r) {
+            return Vec::new();
+        }
+        self.recount(l, r, |v| *v -= 1)
+    }
+    fn recount(&mut self, l: isize, r: isize, change: impl Fn(&mut usize)) -> Vec<usize> {
+        let mut flipped = Vec::new();
+        for id in self.affected(l, r) {
+            if self.spans[id][&r].0 < self.spans[id][&l].1 {
+                let was_valid = self.is_valid(id);
+                change(&mut self.violations[id]);
+                if was_valid != self.is_valid(id) {
+                    flipped.push(id);
+                }
+            }
+        }
+        flipped
+    }
+    fn is_valid(&self, id: usize) -> bool {
+        self.violations[id] == 0
+    }
+    fn is_before(&self, a: isize, b: isize) -> bool {
+        self.rules.is_before(a, b)
+    }
+    fn middle_sum(&self) -> isize {
+        self.updates
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| self.is_valid(*id))
+            .map(|(_, u)| u[u.len() / 2])
+            .sum()
+    }
+}
+
 fn parse(path: &str) -> (Vec<(isize, isize)>, Vec<Vec<isize>>) {
     let content = fs::read_to_string(path).expect("File not found");
     let (order_raw, updates_raw) = content.split_once("\n\n").expect("Expected two parts");
@@ -34,6 +544,7 @@ fn is_ordered(update: &[isize], order: &HashSet<(isize, isize)>) -> bool {
     update.is_sorted_by(|&a, &b| order.contains(&(a, b)))
 }
 
+#[allow(dead_code)]
 fn make_order(pairs: &[(isize, isize)]) -> HashSet<(isize, isize)> {
     let mut items: HashSet<isize> = HashSet::new();
     let mut order: HashSet<(isize, isize)> = HashSet::new();
@@ -77,31 +588,87 @@ fn compare_pages(a: isize, b: isize, order: &HashSet<(isize, isize)>) -> Orderin
     }
 }
 
-fn part1(updates: &[Vec<isize>], order: &HashSet<(isize, isize)>) -> isize {
+fn part1(updates: &[Vec<isize>], order: &impl PageOrder) -> isize {
     updates
         .iter()
-        .filter(|u| is_ordered(u, order))
+        .filter(|u| order.is_ordered(u))
         .map(|u| u[u.len() / 2])
         .sum()
 }
 
-fn part2(updates: &mut [Vec<isize>], order: &HashSet<(isize, isize)>) -> isize {
+fn part2(updates: &mut [Vec<isize>], order: &impl PageOrder) -> isize {
     let mut filtered: Vec<Vec<isize>> = updates
         .iter()
-        .filter(|u| !is_ordered(u, order))
+        .filter(|u| !order.is_ordered(u))
         .cloned()
         .collect();
-    filtered
-        .iter_mut()
-        .for_each(|u| u.sort_by(|&a, &b| compare_pages(a, b, order)));
+    filtered.iter_mut().for_each(|u| order.reorder(u));
     filtered.iter().map(|u| u[u.len() / 2]).sum()
 }
 
 fn main() {
     let (pairs, mut updates) = parse("input.txt");
-    let order = make_order(&pairs);
+    let order = RuleGraph::new(&pairs);
     println!("Part 1: {}", part1(&updates, &order));
     println!("Part 2: {}", part2(&mut updates, &order));
+    let args: Vec<String> = env::args().collect();
+    let changes: Vec<(&String, &String)> = args
+        .iter()
+        .zip(args.iter().skip(1))
+        .filter(|(flag, _)| *flag == "--add" || *flag == "--remove")
+        .collect();
+    if !changes.is_empty() {
+        let mut queue = PrintQueue::new(order.clone(), updates.clone());
+        for (flag, rule) in changes {
+            let (l, r) = rule
+                .split_once('|')
+                .expect("Expected a pair separated by |");
+            let (l, r) = (
+                l.parse().expect("Expected integer"),
+                r.parse().expect("Expected integer"),
+            );
+            let flipped = match flag.as_str() {
+                "--add" => queue.add_rule(l, r),
+                _ => queue.remove_rule(l, r),
+            };
+            println!(
+                "{flag} {rule}: {} update(s) changed validity, {l} before {r}: {}, Part 1: {}",
+                flipped.len(),
+                queue.is_before(l, r),
+                queue.middle_sum()
+            );
+        }
+    }
+    if args.iter().any(|a| a == "--explain") {
+        let json = args.iter().any(|a| a == "--json");
+        for u in updates.iter().filter(|u| !order.is_ordered(u)) {
+            match order.explain(u) {
+                Some(d) if json => println!("{}", d.to_json()),
+                Some(d) => println!("{d}"),
+                None => println!("{}: rules restricted to update are cyclic", join(u)),
+            }
+        }
+    }
+    if args.iter().any(|a| a == "--orderings") {
+        for u in updates.iter() {
+            let middle = match order.unique_middle(u) {
+                Some(p) => format!("middle page {p}"),
+                None => "middle page ambiguous".to_string(),
+            };
+            match order
+                .count_orderings(u)
+                .and_then(|n| Ok((n, order.orderings(u, 3)?)))
+            {
+                Ok((n, orderings)) => {
+                    println!("{}: {n} ordering(s), {middle}", join(u));
+                    for o in orderings {
+                        println!("  {}", join(&o));
+                    }
+                }
+                Err(e) => println!("{}: {e}", join(u)),
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -121,4 +688,130 @@ mod test {
         let order = make_order(&pairs);
         assert_eq!(part2(&mut updates, &order), 123)
     }
+
+    #[test]
+    fn test_rule_graph() {
+        let (pairs, mut updates) = parse("test.txt");
+        let order = RuleGraph::new(&pairs);
+        assert_eq!(part1(&updates, &order), 143);
+        assert_eq!(part2(&mut updates, &order), 123);
+        assert_eq!(order.topo_sort(&[1, 2]), Some(vec![1, 2]));
+        assert_eq!(RuleGraph::new(&[(1, 2), (2, 1)]).topo_sort(&[1, 2]), None);
+
+        let mut update = vec![53, 97, 53, 75, 97];
+        assert_eq!(order.topo_sort(&update), Some(vec![97, 75, 53]));
+        order.reorder(&mut update);
+        assert_eq!(update, vec![97, 97, 75, 53, 53]);
+        assert!(order.is_ordered(&update));
+        let mut update = vec![53, 97, 53];
+        assert!(!order.is_ordered(&update));
+        order.reorder(&mut update);
+        assert_eq!(update, vec![97, 53, 53]);
+        assert!(order.is_ordered(&update));
+    }
+
+    #[test]
+    fn test_explain() {
+        let (pairs, updates) = parse("test.txt");
+        let order = RuleGraph::new(&pairs);
+        let diagnostics: Vec<Diagnostic> =
+            updates.iter().filter_map(|u| order.explain(u)).collect();
+        assert!(diagnostics[..3]
+            .iter()
+            .all(|d| d.violations.is_empty() && d.moves.is_empty()));
+        assert_eq!(
+            diagnostics[3].to_json(),
+            "{\"update\":[75,97,47,61,53],\"violations\":[{\"rule\":[97,75],\"positions\":[1,0]}],\
+             \"moves\":[{\"page\":97,\"from\":1,\"to\":0}],\"sorted\":[97,75,47,61,53]}"
+        );
+        assert_eq!(diagnostics[4].sorted, vec![61, 29, 13]);
+        assert_eq!(diagnostics[5].moves.len(), 2);
+        assert!(diagnostics.iter().all(|d| order.is_ordered(&d.sorted)));
+    }
+
+    #[test]
+    fn test_orderings() {
+        let (pairs, updates) = parse("test.txt");
+        let order = RuleGraph::new(&pairs);
+        assert!(updates.iter().all(|u| order.count_orderings(u) == Ok(1)));
+        assert_eq!(order.unique_middle(&updates[4]), Some(29));
+        let order = RuleGraph::new(&[(1, 3), (2, 3), (3, 4), (3, 5)]);
+        assert_eq!(order.count_orderings(&[5, 4, 3, 2, 1]), Ok(4));
+        assert_eq!(order.unique_middle(&[5, 4, 3, 2, 1]), Some(3));
+        assert_eq!(
+            order.orderings(&[5, 4, 3, 2, 1], 2),
+            Ok(vec![vec![2, 1, 3, 5, 4], vec![2, 1, 3, 4, 5]])
+        );
+        assert_eq!(order.unique_middle(&[3, 2, 1]), None);
+        let order = RuleGraph::new(&[(1, 2), (2, 1)]);
+        assert_eq!(order.count_orderings(&[1, 2]), Ok(0));
+        assert_eq!(order.orderings(&[1, 2], 10), Ok(vec![]));
+        // A cycle among a few pages rules out every ordering however many others are free.
+        let update: Vec<isize> = (1..=30).collect();
+        assert_eq!(order.count_orderings(&update), Ok(0));
+        assert_eq!(order.orderings(&update, 10), Ok(vec![]));
+        let update: Vec<isize> = (1..=65).collect();
+        assert!(order.count_orderings(&update).is_err());
+        assert!(order.orderings(&update, 10).is_err());
+    }
+
+    #[test]
+    fn test_print_queue() {
+        let (pairs, updates) = parse("test.txt");
+        let mut queue = PrintQueue::new(RuleGraph::new(&pairs), updates);
+        assert_eq!(queue.middle_sum(), 143);
+        assert!(queue.is_before(97, 13) && !queue.is_before(13, 97));
+        assert_eq!(queue.remove_rule(97, 75), vec![3]);
+        assert_eq!(queue.remove_rule(97, 75), vec![]);
+        assert_eq!(queue.middle_sum(), 143 + 47);
+        assert_eq!(queue.add_rule(13, 61), vec![1]);
+        assert_eq!(queue.add_rule(29, 75), vec![0, 2]);
+        assert_eq!(queue.middle_sum(), 47);
+        assert!(queue.is_before(13, 47));
+
+        // Repeated pages: each 53 after a 97 breaks 97|53, but it is still one broken rule.
+        let mut queue = PrintQueue::new(
+            RuleGraph::new(&[(97, 53)]),
+            vec![vec![53, 97, 53, 53], vec![97, 53, 53]],
+        );
+        assert_eq!(queue.violations, vec![1, 0]);
+        assert_eq!(queue.remove_rule(97, 53), vec![0]);
+        assert_eq!(queue.violations, vec![0, 0]);
+        assert_eq!(queue.add_rule(53, 97), vec![0, 1]);
+        assert_eq!(queue.add_rule(97, 53), vec![]);
+        assert_eq!(queue.violations, vec![2, 1]);
+        let recounted = PrintQueue::new(queue.rules.clone(), queue.updates.clone());
+        assert_eq!(queue.violations, recounted.violations);
+    }
+
+    fn total_order(n: isize) -> (Vec<(isize, isize)>, Vec<Vec<isize>>) {
+        let pairs = (0..n)
+            .flat_map(|l| (l + 1..n).map(move |r| (l, r)))
+            .collect();
+        let updates = (1..n / 2)
+            .map(|step| (0..n).rev().step_by(step as usize).take(25).collect())
+            .collect();
+        (pairs, updates)
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_rule_graph_vs_closure() {
+        use std::time::Instant;
+        for n in [50, 100, 200] {
+            let (pairs, mut updates) = total_order(n);
+            let start = Instant::now();
+            let order = make_order(&pairs);
+            let closure = (part1(&updates, &order), part2(&mut updates, &order));
+            let closure_time = start.elapsed();
+            let start = Instant::now();
+            let order = RuleGraph::new(&pairs);
+            let topo = (part1(&updates, &order), part2(&mut updates, &order));
+            let topo_time = start.elapsed();
+            assert_eq!(closure, topo);
+            println!(
+                "n = {n:>4}: closure {closure_time:>12.3?}, topological sort {topo_time:>12.3?}"
+            );
+        }
+    }
 }
@@ -1,7 +1,17 @@
-use std::{collections::HashSet, fs};
+use std::{
+    collections::HashSet,
+    env, fs,
+    io::{self, Read},
+    process::{self, Command, Stdio},
+    str::FromStr,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 enum Direction {
+    #[default]
     North = 0,
     East = 1,
     South = 2,
@@ -12,6 +22,28 @@ impl Direction {
     fn rotate(&mut self) {
         *self = Self::from(((*self as usize) + 1) % 4);
     }
+    fn offset(&self) -> (isize, isize) {
+        match self {
+            Self::North => (0, -1),
+            Self::East => (1, 0),
+            Self::South => (0, 1),
+            Self::West => (-1, 0),
+        }
+    }
+}
+
+impl TryFrom<char> for Direction {
+    type Error = char;
+
+    fn try_from(value: char) -> Result<Self, Self::Error> {
+        match value {
+            '^' => Ok(Self::North),
+            '>' => Ok(Self::East),
+            'v' => Ok(Self::South),
+            '<' => Ok(Self::West),
+            _ => Err(value),
+        }
+    }
 }
 
 impl From<usize> for Direction {
@@ -26,15 +58,123 @@ impl From<usize> for Direction {
     }
 }
 
-impl Default for Direction {
-    fn default() -> Self {
-        Self::North
+#[derive(Debug, Clone, Copy, Default)]
+enum TurnRule {
+    #[default]
+    Right,
+    Left,
+    Around,
+    #[allow(dead_code)]
+    Custom(fn(Direction) -> Direction),
+}
+
+impl TurnRule {
+    fn apply(&self, mut direction: Direction) -> Direction {
+        match self {
+            Self::Right => direction.rotate(),
+            Self::Left => (0..3).for_each(|_| direction.rotate()),
+            Self::Around => (0..2).for_each(|_| direction.rotate()),
+            Self::Custom(turn) => direction = turn(direction),
+        }
+        direction
+    }
+}
+
+impl FromStr for TurnRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "right" => Ok(Self::Right),
+            "left" => Ok(Self::Left),
+            "around" => Ok(Self::Around),
+            _ => Err(format!("Unknown turn rule {s}")),
+        }
     }
 }
 
+// A loop carries one full period of guard states, starting with the first turn that gets
+// repeated, so its length is the number of states in the period.
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum GuardTrajectory {
     LeavesArea,
-    Loop,
+    Loop(Vec<((isize, isize), Direction)>),
+}
+
+enum Step {
+    Moved,
+    Turned,
+    Done(GuardTrajectory),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CollisionKind {
+    SameCell,
+    Swap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Collision {
+    tick: usize,
+    guards: (usize, usize),
+    position: (isize, isize),
+    kind: CollisionKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Patrol {
+    outcomes: Vec<GuardTrajectory>,
+    collisions: Vec<Collision>,
+    ticks: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct BitSet(Vec<u64>);
+
+impl BitSet {
+    fn new(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)])
+    }
+    fn contains(&self, k: usize) -> bool {
+        self.0[k / 64] >> (k % 64) & 1 == 1
+    }
+    fn insert(&mut self, k: usize) -> bool {
+        let new = !self.contains(k);
+        self.0[k / 64] |= 1 << (k % 64);
+        new
+    }
+    fn remove(&mut self, k: usize) {
+        self.0[k / 64] &= !(1 << (k % 64));
+    }
+    fn clear(&mut self) {
+        self.0.fill(0);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Walls {
+    width: usize,
+    bits: BitSet,
+}
+
+impl Walls {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            bits: BitSet::new(width * height),
+        }
+    }
+    fn contains(&self, (x, y): (isize, isize)) -> bool {
+        self.bits.contains(y as usize * self.width + x as usize)
+    }
+    fn set(&mut self, (x, y): (isize, isize), wall: bool) {
+        let k = y as usize * self.width + x as usize;
+        if wall {
+            self.bits.insert(k);
+        } else {
+            self.bits.remove(k);
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -43,114 +183,544 @@ struct LabArea {
     height: isize,
     guard: (isize, isize),
     start: (isize, isize),
+    start_direction: Direction,
     guard_direction: Direction,
-    guard_states: HashSet<((isize, isize), Direction)>,
+    guards: Vec<((isize, isize), Direction)>,
+    turn: TurnRule,
+    // Turns taken so far, indexed by obstacle position and heading.
+    guard_states: BitSet,
     guard_trajectory: Vec<((isize, isize), Direction)>,
-    grid: Vec<Vec<char>>,
-    dont_track_guard: bool,
+    walls: Walls,
 }
 
 impl LabArea {
+    fn step(&mut self) -> Step {
+        let (dx, dy) = self.guard_direction.offset();
+        let next_pos = (self.guard.0 + dx, self.guard.1 + dy);
+        if next_pos.0 < 0 || next_pos.0 >= self.width || next_pos.1 < 0 || next_pos.1 >= self.height
+        {
+            return Step::Done(GuardTrajectory::LeavesArea);
+        }
+        if self.walls.contains(next_pos) {
+            if !self
+                .guard_states
+                .insert(4 * self.index(next_pos) + self.guard_direction as usize)
+            {
+                let since = self.first_turn(self.guard, self.guard_direction);
+                return Step::Done(GuardTrajectory::Loop(
+                    self.guard_trajectory[since..].to_vec(),
+                ));
+            }
+            self.guard_direction = self.turn.apply(self.guard_direction);
+            self.guard_trajectory
+                .push((self.guard, self.guard_direction));
+            return Step::Turned;
+        }
+        self.guard = next_pos;
+        self.guard_trajectory.push((next_pos, self.guard_direction));
+        Step::Moved
+    }
+    // Index of the trajectory state right after the first turn at `pos` with heading `direction`,
+    // i.e. the guard did not leave `pos` in between.
+    fn first_turn(&self, pos: (isize, isize), direction: Direction) -> usize {
+        let turned = (pos, self.turn.apply(direction));
+        let mut previous = (self.start, self.start_direction);
+        for (k, state) in self.guard_trajectory.iter().enumerate() {
+            if previous == (pos, direction) && *state == turned {
+                return k;
+            }
+            previous = *state;
+        }
+        unreachable!("Turn at {pos:?} missing from the trajectory")
+    }
     fn move_guard(&mut self) -> GuardTrajectory {
-        let mut next_pos;
         loop {
-            next_pos = match self.guard_direction {
-                Direction::North => (self.guard.0, self.guard.1 - 1),
-                Direction::East => (self.guard.0 + 1, self.guard.1),
-                Direction::South => (self.guard.0, self.guard.1 + 1),
-                Direction::West => (self.guard.0 - 1, self.guard.1),
-            };
-            if next_pos.0 < 0
-                || next_pos.0 >= self.width
-                || next_pos.1 < 0
-                || next_pos.1 >= self.height
-            {
-                return GuardTrajectory::LeavesArea;
+            if let Step::Done(trajectory) = self.step() {
+                return trajectory;
             }
-            if self.grid[next_pos.1 as usize][next_pos.0 as usize] == '#' {
-                if !self.guard_states.insert((next_pos, self.guard_direction)) {
-                    return GuardTrajectory::Loop;
-                } 
-                self.guard_direction.rotate();
-                continue;
+        }
+    }
+    // Every guard takes one step (a move or a turn) per tick; guards do not block each other.
+    // A looping guard keeps walking its cycle, and the patrol ends once no guard is left that
+    // might still leave the area.
+    fn patrol(&self) -> Patrol {
+        let mut walkers: Vec<LabArea> = self
+            .guards
+            .iter()
+            .map(|&(pos, direction)| {
+                let mut lab = self.clone();
+                lab.guard = pos;
+                lab.start = pos;
+                lab.guard_direction = direction;
+                lab.start_direction = direction;
+                lab.guard_states.clear();
+                lab.guard_trajectory.clear();
+                lab
+            })
+            .collect();
+        let mut outcomes: Vec<Option<GuardTrajectory>> = vec![None; walkers.len()];
+        let mut positions: Vec<Option<(isize, isize)>> =
+            self.guards.iter().map(|(pos, _)| Some(*pos)).collect();
+        let mut cycle_steps = vec![0; walkers.len()];
+        let mut collisions = Vec::new();
+        let mut ticks = 0;
+        while outcomes.iter().any(Option::is_none) {
+            ticks += 1;
+            let before = positions.clone();
+            for (k, walker) in walkers.iter_mut().enumerate() {
+                match &outcomes[k] {
+                    Some(GuardTrajectory::LeavesArea) => {}
+                    Some(GuardTrajectory::Loop(cycle)) => {
+                        positions[k] = Some(cycle[cycle_steps[k] % cycle.len()].0);
+                        cycle_steps[k] += 1;
+                    }
+                    None => match walker.step() {
+                        Step::Done(GuardTrajectory::LeavesArea) => {
+                            positions[k] = None;
+                            outcomes[k] = Some(GuardTrajectory::LeavesArea);
+                        }
+                        Step::Done(GuardTrajectory::Loop(cycle)) => {
+                            positions[k] = Some(cycle[0].0);
+                            cycle_steps[k] = 1;
+                            outcomes[k] = Some(GuardTrajectory::Loop(cycle));
+                        }
+                        Step::Moved | Step::Turned => positions[k] = Some(walker.guard),
+                    },
+                }
             }
-            self.guard = next_pos;
-            if !self.dont_track_guard {
-                self.guard_trajectory.push((next_pos, self.guard_direction));
+            for i in 0..positions.len() {
+                for j in i + 1..positions.len() {
+                    let (Some(p), Some(q)) = (positions[i], positions[j]) else {
+                        continue;
+                    };
+                    let kind = if p == q {
+                        CollisionKind::SameCell
+                    } else if before[i] == Some(q) && before[j] == Some(p) {
+                        CollisionKind::Swap
+                    } else {
+                        continue;
+                    };
+                    collisions.push(Collision {
+                        tick: ticks,
+                        guards: (i, j),
+                        position: p,
+                        kind,
+                    });
+                }
             }
         }
+        Patrol {
+            outcomes: outcomes.into_iter().flatten().collect(),
+            collisions,
+            ticks,
+        }
+    }
+    fn index(&self, (x, y): (isize, isize)) -> usize {
+        (y * self.width + x) as usize
     }
-    fn guard_positions(&self) -> HashSet<(isize, isize)> {
-        let mut guard_positions = HashSet::new();
-        for (pos, _) in &self.guard_trajectory {
-            guard_positions.insert(*pos);
+    // Distinct cells the guard has been on, in order of the first visit.
+    fn guard_positions(&self) -> Vec<(isize, isize)> {
+        let mut seen = BitSet::new((self.width * self.height) as usize);
+        let mut guard_positions = Vec::new();
+        let start = (self.start, self.start_direction);
+        for (pos, _) in std::iter::once(&start).chain(self.guard_trajectory.iter()) {
+            if seen.insert(self.index(*pos)) {
+                guard_positions.push(*pos);
+            }
         }
         guard_positions
     }
-    fn reset(&mut self) {
-        self.guard_trajectory.clear();
-        self.guard_states.clear();
-        self.guard = self.start;
-        self.guard_direction = Direction::North;
+}
+
+// For every free cell and heading, the cell in which the guard has to stop in front of the next
+// obstacle, or None if the guard walks off the map. A single extra obstruction is accounted for
+// on the fly, so the table is shared by all candidate obstructions.
+#[derive(Debug, Clone)]
+struct JumpTable {
+    width: isize,
+    height: isize,
+    stops: Vec<[Option<(isize, isize)>; 4]>,
+    turn: TurnRule,
+}
+
+impl JumpTable {
+    fn new(lab: &LabArea) -> Self {
+        let mut table = Self {
+            width: lab.width,
+            height: lab.height,
+            stops: vec![[None; 4]; (lab.width * lab.height) as usize],
+            turn: lab.turn,
+        };
+        for d in 0..4 {
+            let (dx, dy) = Direction::from(d).offset();
+            let mut ys: Vec<isize> = (0..lab.height).collect();
+            let mut xs: Vec<isize> = (0..lab.width).collect();
+            if dy > 0 {
+                ys.reverse();
+            }
+            if dx > 0 {
+                xs.reverse();
+            }
+            for &y in ys.iter() {
+                for &x in xs.iter() {
+                    let next = (x + dx, y + dy);
+                    let idx = table.index((x, y));
+                    table.stops[idx][d] = if !table.contains(next) {
+                        None
+                    } else if lab.walls.contains(next) {
+                        Some((x, y))
+                    } else {
+                        table.stops[table.index(next)][d]
+                    };
+                }
+            }
+        }
+        table
+    }
+    fn contains(&self, (x, y): (isize, isize)) -> bool {
+        x >= 0 && x < self.width && y >= 0 && y < self.height
+    }
+    fn index(&self, (x, y): (isize, isize)) -> usize {
+        (y * self.width + x) as usize
+    }
+    fn jump(
+        &self,
+        pos: (isize, isize),
+        direction: Direction,
+        obstruction: (isize, isize),
+    ) -> Option<(isize, isize)> {
+        let stop = self.stops[self.index(pos)][direction as usize];
+        let (dx, dy) = direction.offset();
+        let (ox, oy) = (obstruction.0 - pos.0, obstruction.1 - pos.1);
+        let k = ox * dx + oy * dy;
+        if k > 0 && (ox, oy) == (k * dx, k * dy) {
+            let limit = stop.map_or(isize::MAX, |(sx, sy)| (sx - pos.0) * dx + (sy - pos.1) * dy);
+            if k <= limit {
+                return Some((obstruction.0 - dx, obstruction.1 - dy));
+            }
+        }
+        stop
+    }
+    // `seen` is scratch space shared between calls, a state counts as seen in this walk if it
+    // carries the current `stamp`.
+    fn loops(
+        &self,
+        start: (isize, isize),
+        mut direction: Direction,
+        obstruction: (isize, isize),
+        seen: &mut [u32],
+        stamp: u32,
+    ) -> bool {
+        let mut pos = start;
+        while let Some(stop) = self.jump(pos, direction, obstruction) {
+            pos = stop;
+            let state = 4 * self.index(pos) + direction as usize;
+            if seen[state] == stamp {
+                return true;
+            }
+            seen[state] = stamp;
+            direction = self.turn.apply(direction);
+        }
+        false
+    }
+}
+
+// Walks the guard one cell at a time for display purposes. With an obstruction this is the part
+// 2 check for a single candidate, and if it makes the guard loop, the cells of the loop are kept
+// for highlighting.
+struct Replay {
+    lab: LabArea,
+    obstruction: Option<(isize, isize)>,
+    visited: HashSet<(isize, isize)>,
+    cycle: HashSet<(isize, isize)>,
+    outcome: Option<GuardTrajectory>,
+    steps: usize,
+}
+
+impl Replay {
+    fn new(lab: &LabArea, obstruction: Option<(isize, isize)>) -> Result<Self, String> {
+        let mut lab = lab.clone();
+        if let Some((x, y)) = obstruction {
+            if !(0..lab.width).contains(&x) || !(0..lab.height).contains(&y) {
+                return Err(format!(
+                    "Obstruction ({x},{y}) is outside the {}x{} lab",
+                    lab.width, lab.height
+                ));
+            }
+            if (x, y) == lab.start {
+                return Err(format!("Obstruction ({x},{y}) is on the guard's start"));
+            }
+            lab.walls.set((x, y), true);
+        }
+        Ok(Self {
+            visited: HashSet::from([lab.guard]),
+            lab,
+            obstruction,
+            cycle: HashSet::new(),
+            outcome: None,
+            steps: 0,
+        })
+    }
+    fn advance(&mut self) -> bool {
+        if self.outcome.is_some() {
+            return false;
+        }
+        match self.lab.step() {
+            Step::Moved => {
+                self.visited.insert(self.lab.guard);
+            }
+            Step::Turned => {}
+            Step::Done(trajectory) => {
+                if let GuardTrajectory::Loop(cycle) = &trajectory {
+                    self.cycle = cycle.iter().map(|(pos, _)| *pos).collect();
+                }
+                self.outcome = Some(trajectory);
+            }
+        }
+        self.steps += 1;
+        self.outcome.is_none()
+    }
+}
+
+impl std::fmt::Display for Replay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut rows = Vec::new();
+        for y in 0..self.lab.height {
+            let mut line = String::new();
+            for x in 0..self.lab.width {
+                let pos = (x, y);
+                let cell =
+                    if pos == self.lab.guard && self.outcome != Some(GuardTrajectory::LeavesArea) {
+                        let heading = ['^', '>', 'v', '<'][self.lab.guard_direction as usize];
+                        format!("\x1b[1;32m{heading}")
+                    } else if Some(pos) == self.obstruction {
+                        "\x1b[1;31mO".to_string()
+                    } else if self.lab.walls.contains(pos) {
+                        "\x1b[0;37m#".to_string()
+                    } else if self.cycle.contains(&pos) {
+                        "\x1b[1;35mX".to_string()
+                    } else if self.visited.contains(&pos) {
+                        "\x1b[0;33mX".to_string()
+                    } else {
+                        "\x1b[38;5;0m.".to_string()
+                    };
+                line.push_str(&cell);
+            }
+            rows.push(line);
+        }
+        let status = match &self.outcome {
+            None => "walking".to_string(),
+            Some(GuardTrajectory::LeavesArea) => "left the area".to_string(),
+            Some(GuardTrajectory::Loop(cycle)) => {
+                format!("stuck in a loop of length {}", cycle.len())
+            }
+        };
+        write!(
+            f,
+            "\x1b[1;1H{}\n\x1b[0;37mstep {}, {} cells visited, guard {status}\x1b[K",
+            rows.join("\n"),
+            self.steps,
+            self.visited.len()
+        )
+    }
+}
+
+// Keys: space pauses and resumes, n steps while paused, + and - change the speed, q quits.
+// Without a terminal to read keys from, or once stdin ends, the walk plays through on its own.
+fn replay(mut replay: Replay) {
+    let raw = Command::new("stty")
+        .args(["-icanon", "-echo"])
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success());
+    let (tx, rx) = mpsc::channel();
+    if raw {
+        thread::spawn(move || {
+            for key in io::stdin().lock().bytes() {
+                if key.map_or(true, |k| tx.send(k).is_err()) {
+                    break;
+                }
+            }
+        });
+    } else {
+        drop(tx);
+    }
+    let mut delay = Duration::from_millis(50);
+    let mut paused = false;
+    print!("\x1b[2J\x1b[?25l");
+    loop {
+        println!(
+            "{replay}\n{:>5} ms per step{}\x1b[K",
+            delay.as_millis(),
+            if paused { ", paused" } else { "" }
+        );
+        let key = if paused {
+            // With no keys left, nothing can resume the walk.
+            match rx.recv() {
+                Ok(key) => key,
+                Err(_) => break,
+            }
+        } else {
+            match rx.recv_timeout(delay) {
+                Ok(key) => key,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    paused = !replay.advance();
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    thread::sleep(delay);
+                    paused = !replay.advance();
+                    continue;
+                }
+            }
+        };
+        match key {
+            b' ' => paused = !paused,
+            b'n' => {
+                replay.advance();
+            }
+            b'+' => delay = (delay / 2).max(Duration::from_millis(1)),
+            b'-' => delay = (delay * 2).min(Duration::from_secs(2)),
+            b'q' => break,
+            _ => {}
+        }
+    }
+    print!("\x1b[0m\x1b[?25h");
+    if raw {
+        let _ = Command::new("stty").args(["icanon", "echo"]).status();
     }
 }
 
 fn part1(lab: &LabArea) -> usize {
     let mut lab = lab.clone();
-    lab.dont_track_guard = false;
     lab.move_guard();
     lab.guard_positions().len()
 }
 
-fn part2(lab: &mut LabArea) -> usize {
-    let mut res = 0;
+// The obstruction is placed while the guard stands on the start cell, so that cell is ruled out.
+// Any cell off the original path leaves the walk unchanged, which leaves the other cells of the
+// path as candidates.
+fn part2(lab: &mut LabArea) -> HashSet<(isize, isize)> {
     lab.move_guard();
-    lab.dont_track_guard = true;
-    let positions_and_directions = lab.guard_trajectory.clone();
-    for ((x, y), _) in positions_and_directions.iter().skip(2) {
-        lab.reset();
-        lab.grid[*y as usize][*x as usize] = '#';
-        match lab.move_guard() {
-            GuardTrajectory::Loop => res += 1,
-            GuardTrajectory::LeavesArea => {}
-        }
-        lab.grid[*y as usize][*x as usize] = '.';
-    }
-    res - 1
+    let candidates: Vec<(isize, isize)> = lab
+        .guard_positions()
+        .into_iter()
+        .filter(|p| *p != lab.start)
+        .collect();
+    let table = JumpTable::new(lab);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = candidates.len().div_ceil(threads).max(1);
+    thread::scope(|s| {
+        let workers: Vec<_> = candidates
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let table = &table;
+                let (start, direction) = (lab.start, lab.start_direction);
+                s.spawn(move || {
+                    let mut seen = vec![0; table.stops.len() * 4];
+                    chunk
+                        .iter()
+                        .enumerate()
+                        .filter(|(k, o)| {
+                            table.loops(start, direction, **o, &mut seen, *k as u32 + 1)
+                        })
+                        .map(|(_, o)| *o)
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("Worker panicked"))
+            .collect()
+    })
 }
 
-fn load(path: &str) -> LabArea {
-    let mut result = LabArea::default();
-    let raw = fs::read_to_string(path).expect("File not found");
-    for (y, row) in raw.split('\n').enumerate() {
-        result.width = row.len() as isize;
-        result.height += 1;
-        result.grid.push(vec![]);
+fn parse(raw: &str) -> LabArea {
+    let rows: Vec<&str> = raw.split('\n').collect();
+    let width = rows[0].len();
+    let mut result = LabArea {
+        width: width as isize,
+        height: rows.len() as isize,
+        guard_states: BitSet::new(4 * width * rows.len()),
+        walls: Walls::new(width, rows.len()),
+        ..Default::default()
+    };
+    for (y, row) in rows.iter().enumerate() {
         for (x, c) in row.chars().enumerate() {
+            let pos = (x as isize, y as isize);
             match c {
-                '#' => {
-                    // result.grid(x as isize, y as isize);
-                }
-                '^' => {
-                    result.guard = (x as isize, y as isize);
-                    result.start = (x as isize, y as isize);
-                    result
-                        .guard_states
-                        .insert(((x as isize, y as isize), Direction::North));
-                }
+                '#' => result.walls.set(pos, true),
                 '.' => {}
-                _ => unreachable!(),
+                _ => {
+                    let direction = Direction::try_from(c).expect("Unexpected character");
+                    if result.guards.is_empty() {
+                        result.guard = pos;
+                        result.start = pos;
+                        result.guard_direction = direction;
+                        result.start_direction = direction;
+                    }
+                    result.guards.push((pos, direction));
+                }
             }
-            result.grid[y].push(c);
         }
     }
     result
 }
 
+fn load(path: &str) -> LabArea {
+    parse(&fs::read_to_string(path).expect("File not found"))
+}
+
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    if let Some(k) = args.iter().position(|a| a == "--replay") {
+        let lab = load(args.get(k + 1).map_or("input.txt", |p| p.as_str()));
+        let obstruction = args.get(k + 2).map(|o| {
+            let (x, y) = o.split_once(',').expect("Expected an obstruction x,y");
+            (
+                x.parse().expect("Expected an isize"),
+                y.parse().expect("Expected an isize"),
+            )
+        });
+        match Replay::new(&lab, obstruction) {
+            Ok(r) => replay(r),
+            Err(e) => {
+                eprintln!("{e}");
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(k) = args.iter().position(|a| a == "--patrol") {
+        let mut lab = load(args.get(k + 1).map_or("input.txt", |p| p.as_str()));
+        if let Some(t) = args.iter().position(|a| a == "--turn") {
+            lab.turn = args
+                .get(t + 1)
+                .expect("Expected a turn rule")
+                .parse()
+                .expect("Invalid turn rule");
+        }
+        let patrol = lab.patrol();
+        for ((pos, _), outcome) in lab.guards.iter().zip(patrol.outcomes.iter()) {
+            match outcome {
+                GuardTrajectory::LeavesArea => println!("Guard from {pos:?} leaves the area"),
+                GuardTrajectory::Loop(cycle) => {
+                    println!("Guard from {pos:?} loops with length {}", cycle.len())
+                }
+            }
+        }
+        for c in patrol.collisions.iter() {
+            println!(
+                "Tick {}: guards {} and {} collide at {:?} ({:?})",
+                c.tick, c.guards.0, c.guards.1, c.position, c.kind
+            );
+        }
+        return;
+    }
     let mut lab = load("input.txt");
     println!("Part 1: {}", part1(&lab));
-    println!("Part 2: {}", part2(&mut lab));
+    println!("Part 2: {}", part2(&mut lab).len());
 }
 
 #[cfg(test)]
@@ -166,6 +736,173 @@ mod test {
     #[test]
     fn test_part2() {
         let mut lab = load("test.txt");
-        assert_eq!(part2(&mut lab), 6)
+        assert_eq!(part2(&mut lab).len(), 6)
+    }
+
+    #[test]
+    fn test_obstructions() {
+        let mut lab = load("test.txt");
+        let obstructions = part2(&mut lab.clone());
+        let expected = HashSet::from([(3, 6), (6, 7), (7, 7), (1, 8), (3, 8), (7, 9)]);
+        assert_eq!(obstructions, expected);
+        lab.walls.set((3, 6), true);
+        match lab.move_guard() {
+            GuardTrajectory::Loop(cycle) => {
+                assert_eq!(cycle.len(), 22);
+                assert_eq!(cycle[0], ((4, 1), Direction::East));
+                assert_eq!(cycle[cycle.len() - 1], ((4, 1), Direction::North));
+            }
+            GuardTrajectory::LeavesArea => panic!("Expected a loop"),
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let lab = load("test.txt");
+        let mut replay = Replay::new(&lab, None).unwrap();
+        while replay.advance() {}
+        assert_eq!(replay.outcome, Some(GuardTrajectory::LeavesArea));
+        assert_eq!(replay.visited.len(), 41);
+        let mut replay = Replay::new(&lab, Some((3, 6))).unwrap();
+        while replay.advance() {}
+        assert!(matches!(replay.outcome, Some(GuardTrajectory::Loop(_))));
+        assert_eq!(replay.cycle.len(), 18);
+        assert!(format!("{replay}").contains("\x1b[1;31mO"));
+        for bad in [(10, 0), (0, 10), (-1, 3), (100, 100), lab.start] {
+            assert!(Replay::new(&lab, Some(bad)).is_err());
+        }
+        assert!(Replay::new(&lab, Some((9, 9))).is_ok());
+    }
+
+    #[test]
+    fn test_turn_rules() {
+        let mut lab = load("test.txt");
+        lab.turn = TurnRule::Left;
+        assert_eq!(part1(&lab), 10);
+        lab.turn = TurnRule::Around;
+        assert_eq!(part1(&lab), 9);
+        lab.turn = TurnRule::Custom(|d| d);
+        assert!(matches!(lab.clone().move_guard(), GuardTrajectory::Loop(c) if c.len() == 1));
+        let patrol = load("test_guards.txt").patrol();
+        assert_eq!(patrol.ticks, 5);
+        assert!(patrol
+            .outcomes
+            .iter()
+            .all(|o| *o == GuardTrajectory::LeavesArea));
+        assert_eq!(
+            patrol.collisions,
+            vec![
+                Collision {
+                    tick: 2,
+                    guards: (0, 1),
+                    position: (2, 1),
+                    kind: CollisionKind::SameCell
+                },
+                Collision {
+                    tick: 2,
+                    guards: (2, 3),
+                    position: (2, 2),
+                    kind: CollisionKind::Swap
+                }
+            ]
+        );
+        let mut lab = load("test.txt");
+        lab.walls.set((3, 6), true);
+        assert!(matches!(&lab.patrol().outcomes[0], GuardTrajectory::Loop(c) if c.len() == 22));
+    }
+
+    fn generate(size: usize, seed: u64) -> String {
+        let mut state = seed;
+        let mut rows = Vec::new();
+        for y in 0..size {
+            let row: String = (0..size)
+                .map(|x| {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    if (x, y) == (size / 2, size / 2) {
+                        '^'
+                    } else if state >> 33 < (1 << 31) / 60 {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            rows.push(row);
+        }
+        rows.join("\n")
+    }
+
+    // The walk as it was done on a `Vec<Vec<char>>` grid with hashed guard states.
+    fn reference_walk(raw: &str) -> usize {
+        let grid: Vec<Vec<char>> = raw.split('\n').map(|r| r.chars().collect()).collect();
+        let (w, h) = (grid[0].len() as isize, grid.len() as isize);
+        let y = grid.iter().position(|r| r.contains(&'^')).unwrap();
+        let x = grid[y].iter().position(|c| *c == '^').unwrap();
+        let (mut pos, mut direction) = ((x as isize, y as isize), Direction::North);
+        let mut states = HashSet::new();
+        let mut visited = HashSet::from([pos]);
+        loop {
+            let (dx, dy) = direction.offset();
+            let next = (pos.0 + dx, pos.1 + dy);
+            if next.0 < 0 || next.0 >= w || next.1 < 0 || next.1 >= h {
+                return visited.len();
+            }
+            if grid[next.1 as usize][next.0 as usize] == '#' {
+                if !states.insert((next, direction)) {
+                    return visited.len();
+                }
+                direction.rotate();
+                continue;
+            }
+            pos = next;
+            visited.insert(pos);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_generated_labs() {
+        use std::time::Instant;
+        for size in [500, 1000, 2000, 4000] {
+            let raw = generate(size, 42);
+            let start = Instant::now();
+            let reference = reference_walk(&raw);
+            let reference_time = start.elapsed();
+            let start = Instant::now();
+            let lab = parse(&raw);
+            let visited = part1(&lab);
+            let compact_time = start.elapsed();
+            assert_eq!(reference, visited);
+            println!(
+                "{size:>4}x{size:<4} {visited:>6} cells visited: grid {:>9} bytes in {reference_time:>10.3?}, walls {:>8} bytes in {compact_time:>10.3?}",
+                size * size * std::mem::size_of::<char>(),
+                lab.walls.bits.0.len() * 8,
+            );
+        }
+    }
+
+    #[test]
+    fn test_jump_table() {
+        let lab = load("test.txt");
+        let table = JumpTable::new(&lab);
+        let mut seen = vec![0; table.stops.len() * 4];
+        let mut stamp = 0;
+        for y in 0..lab.height {
+            for x in 0..lab.width {
+                if (x, y) == lab.start || lab.walls.contains((x, y)) {
+                    continue;
+                }
+                let mut blocked = lab.clone();
+                blocked.walls.set((x, y), true);
+                let stepwise = matches!(blocked.move_guard(), GuardTrajectory::Loop(_));
+                stamp += 1;
+                assert_eq!(
+                    table.loops(lab.start, lab.start_direction, (x, y), &mut seen, stamp),
+                    stepwise
+                );
+            }
+        }
     }
 }
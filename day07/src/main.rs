@@ -1,30 +1,449 @@
 use itertools::Itertools;
-use std::fs;
+use num_bigint::BigUint;
+use std::{
+    env, fmt, fs, iter, panic,
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
 
-#[derive(Debug, Clone, Copy)]
+// The numbers equations are solved over. Every operation reports overflow as None, which prunes
+// the branch just like a result that overshoots the target.
+trait Value: Clone + PartialEq + fmt::Display + fmt::Debug + FromStr + Send + Sync {
+    fn from_usize(n: usize) -> Self;
+    fn to_usize(&self) -> Option<usize>;
+    fn try_add(&self, x: &Self) -> Option<Self>;
+    fn try_sub(&self, x: &Self) -> Option<Self>;
+    fn try_mul(&self, x: &Self) -> Option<Self>;
+    fn try_div(&self, x: &Self) -> Option<Self>;
+    fn try_rem(&self, x: &Self) -> Option<Self>;
+    fn try_pow(&self, exp: u32) -> Option<Self>;
+    // The largest r with r^n <= self.
+    fn root(&self, n: u32) -> Self;
+    // 10 to the number of digits of `self`, the factor concatenation shifts the left operand by.
+    fn concat_shift(&self) -> Option<Self> {
+        Self::from_usize(10).try_pow(self.to_string().len() as u32)
+    }
+    fn zero() -> Self {
+        Self::from_usize(0)
+    }
+    fn one() -> Self {
+        Self::from_usize(1)
+    }
+}
+
+impl Value for usize {
+    fn from_usize(n: usize) -> Self {
+        n
+    }
+    fn to_usize(&self) -> Option<usize> {
+        Some(*self)
+    }
+    fn try_add(&self, x: &Self) -> Option<Self> {
+        self.checked_add(*x)
+    }
+    fn try_sub(&self, x: &Self) -> Option<Self> {
+        self.checked_sub(*x)
+    }
+    fn try_mul(&self, x: &Self) -> Option<Self> {
+        self.checked_mul(*x)
+    }
+    fn try_div(&self, x: &Self) -> Option<Self> {
+        self.checked_div(*x)
+    }
+    fn try_rem(&self, x: &Self) -> Option<Self> {
+        self.checked_rem(*x)
+    }
+    fn try_pow(&self, exp: u32) -> Option<Self> {
+        self.checked_pow(exp)
+    }
+    fn root(&self, n: u32) -> Self {
+        let mut r = (*self as f64).powf(1.0 / n as f64) as usize;
+        while r.checked_pow(n).is_none_or(|p| p > *self) {
+            r -= 1;
+        }
+        while (r + 1).checked_pow(n).is_some_and(|p| p <= *self) {
+            r += 1;
+        }
+        r
+    }
+}
+
+// Big integers never overflow, but a power can still take gigabytes, so results past this many
+// bits count as overflow. That is far beyond any target an input could reasonably hold.
+const MAX_BITS: u64 = 1 << 16;
+
+impl Value for BigUint {
+    fn from_usize(n: usize) -> Self {
+        BigUint::from(n)
+    }
+    fn to_usize(&self) -> Option<usize> {
+        usize::try_from(self).ok()
+    }
+    fn try_add(&self, x: &Self) -> Option<Self> {
+        Some(self + x)
+    }
+    fn try_sub(&self, x: &Self) -> Option<Self> {
+        (self >= x).then(|| self - x)
+    }
+    fn try_mul(&self, x: &Self) -> Option<Self> {
+        (self.bits() + x.bits() <= MAX_BITS).then(|| self * x)
+    }
+    fn try_div(&self, x: &Self) -> Option<Self> {
+        (*x != Self::zero()).then(|| self / x)
+    }
+    fn try_rem(&self, x: &Self) -> Option<Self> {
+        (*x != Self::zero()).then(|| self % x)
+    }
+    fn try_pow(&self, exp: u32) -> Option<Self> {
+        (*self <= Self::one() || self.bits().saturating_mul(exp as u64) <= MAX_BITS)
+            .then(|| self.pow(exp))
+    }
+    fn root(&self, n: u32) -> Self {
+        self.nth_root(n)
+    }
+}
+
+// Division preimages wider than this are left to the forward search.
+const MAX_PREIMAGE: usize = 1 << 12;
+
+trait Operator<N = usize>: fmt::Display + Sync {
+    // None if the result is undefined or overflows, e.g. a negative difference or a division by
+    // zero.
+    fn apply(&self, acc: &N, x: &N) -> Option<N>;
+    // Every left operand that `apply` maps to `target` with `x` on the right. Operators that
+    // cannot enumerate these return None, and the search falls back to evaluating the prefix.
+    fn invert(&self, _target: &N, _x: &N) -> Option<Vec<N>> {
+        None
+    }
+    fn precedence(&self) -> u8 {
+        1
+    }
+    fn right_associative(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Op {
     Add,
     Mul,
     Concat,
+    Sub,
+    Div,
+    Pow,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Add => write!(f, "+"),
+            Op::Mul => write!(f, "*"),
+            Op::Concat => write!(f, "||"),
+            Op::Sub => write!(f, "-"),
+            Op::Div => write!(f, "/"),
+            Op::Pow => write!(f, "^"),
+        }
+    }
+}
+
+impl FromStr for Op {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "+" => Ok(Op::Add),
+            "*" => Ok(Op::Mul),
+            "||" => Ok(Op::Concat),
+            "-" => Ok(Op::Sub),
+            "/" => Ok(Op::Div),
+            "^" => Ok(Op::Pow),
+            _ => Err(format!("Unknown operator {s}")),
+        }
+    }
+}
+
+impl<N: Value> Operator<N> for Op {
+    fn apply(&self, acc: &N, x: &N) -> Option<N> {
+        match self {
+            Op::Add => acc.try_add(x),
+            Op::Mul => acc.try_mul(x),
+            Op::Concat => acc.try_mul(&x.concat_shift()?)?.try_add(x),
+            Op::Sub => acc.try_sub(x),
+            Op::Div => acc.try_div(x),
+            Op::Pow => match x.to_usize().and_then(|e| u32::try_from(e).ok()) {
+                Some(exp) => acc.try_pow(exp),
+                // Only 0 and 1 have powers this large that fit, and they are their own powers.
+                None => (*acc == N::zero() || *acc == N::one()).then(|| acc.clone()),
+            },
+        }
+    }
+    fn invert(&self, target: &N, x: &N) -> Option<Vec<N>> {
+        let (zero, one) = (N::zero(), N::one());
+        let preimage = match self {
+            Op::Add => target.try_sub(x).into_iter().collect(),
+            Op::Mul if *x == zero && *target == zero => return None,
+            Op::Mul => (target.try_rem(x) == Some(zero))
+                .then(|| target.try_div(x))
+                .flatten()
+                .into_iter()
+                .collect(),
+            Op::Concat => {
+                let shift = x.concat_shift()?;
+                (target.try_rem(&shift).as_ref() == Some(x))
+                    .then(|| target.try_div(&shift))
+                    .flatten()
+                    .into_iter()
+                    .collect()
+            }
+            Op::Sub => target.try_add(x).into_iter().collect(),
+            Op::Div if *x == zero => Vec::new(),
+            Op::Div => {
+                let width = x.to_usize().filter(|&w| w <= MAX_PREIMAGE)?;
+                match target.try_mul(x) {
+                    Some(low) => (0..width)
+                        .map_while(|k| low.try_add(&N::from_usize(k)))
+                        .collect(),
+                    None => Vec::new(),
+                }
+            }
+            Op::Pow if *x == zero && *target == one => return None,
+            Op::Pow if *x == zero => Vec::new(),
+            Op::Pow => match x.to_usize().and_then(|e| u32::try_from(e).ok()) {
+                Some(exp) => {
+                    let r = target.root(exp);
+                    (r.try_pow(exp).as_ref() == Some(target))
+                        .then_some(r)
+                        .into_iter()
+                        .collect()
+                }
+                None => (*target == zero || *target == one)
+                    .then(|| target.clone())
+                    .into_iter()
+                    .collect(),
+            },
+        };
+        Some(preimage)
+    }
+    // Concatenation binds tightest, like writing the digits next to each other.
+    fn precedence(&self) -> u8 {
+        match self {
+            Op::Add | Op::Sub => 1,
+            Op::Mul | Op::Div => 2,
+            Op::Pow => 3,
+            Op::Concat => 4,
+        }
+    }
+    fn right_associative(&self) -> bool {
+        matches!(self, Op::Pow)
+    }
+}
+
+const PART1_OPS: [&dyn Operator; 2] = [&Op::Add, &Op::Mul];
+const PART2_OPS: [&dyn Operator; 3] = [&Op::Add, &Op::Mul, &Op::Concat];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Order {
+    #[default]
+    LeftToRight,
+    Precedence,
 }
 
-fn load(path: &str) -> Vec<(usize, Vec<usize>)> {
+fn parse<N: Value>(s: &str) -> N {
+    match s.parse() {
+        Ok(n) => n,
+        Err(_) => panic!("Expected a number, got {s}"),
+    }
+}
+
+fn load<N: Value>(path: &str) -> Vec<(N, Vec<N>)> {
     fs::read_to_string(path)
         .expect("File not found")
         .split('\n')
         .map(|r| {
             let (tgt, values) = r.split_once(": ").expect("Unexpected row format");
-            (
-                tgt.parse().expect("Expected usize"),
-                values
-                    .split(' ')
-                    .map(|s| s.parse().expect("Expected usize"))
-                    .collect(),
-            )
+            (parse(tgt), values.split(' ').map(parse).collect())
         })
         .collect()
 }
 
+fn evaluate<N: Value>(rhs: &[N], ops: &[&dyn Operator<N>], order: Order) -> Option<N> {
+    let operands = rhs.iter().skip(1);
+    match order {
+        Order::LeftToRight => ops
+            .iter()
+            .zip(operands)
+            .try_fold(rhs[0].clone(), |acc, (op, x)| op.apply(&acc, x)),
+        Order::Precedence => {
+            fn reduce<N>(values: &mut Vec<N>, op: &dyn Operator<N>) -> Option<()> {
+                let x = values.pop()?;
+                let acc = values.pop()?;
+                values.push(op.apply(&acc, &x)?);
+                Some(())
+            }
+            let mut values = vec![rhs[0].clone()];
+            let mut pending: Vec<&dyn Operator<N>> = Vec::new();
+            for (op, x) in ops.iter().zip(operands) {
+                while let Some(top) = pending.last() {
+                    if top.precedence() < op.precedence()
+                        || top.precedence() == op.precedence() && op.right_associative()
+                    {
+                        break;
+                    }
+                    reduce(&mut values, pending.pop()?)?;
+                }
+                pending.push(*op);
+                values.push(x.clone());
+            }
+            while let Some(op) = pending.pop() {
+                reduce(&mut values, op)?;
+            }
+            values.pop()
+        }
+    }
+}
+
+type Sequence<'a, N> = [&'a dyn Operator<N>];
+
+// Calls `visit` with every operator sequence that makes the equation hold, until it returns
+// false. Left to right, the search works from the last value backwards, so each operator either
+// undoes the final step or rules the whole branch out. With precedence, every sequence is tried.
+fn search<'a, N: Value>(
+    lhs: &N,
+    rhs: &[N],
+    ops: &[&'a dyn Operator<N>],
+    order: Order,
+    visit: &mut dyn FnMut(&[&'a dyn Operator<N>]) -> bool,
+) {
+    fn forward<'a, N: Value>(
+        acc: &N,
+        rhs: &[N],
+        ops: &[&'a dyn Operator<N>],
+        prefix: &mut Vec<&'a dyn Operator<N>>,
+        visit: &mut dyn FnMut(&N, &Sequence<'a, N>) -> bool,
+    ) -> bool {
+        let Some((x, rest)) = rhs.split_first() else {
+            return visit(acc, prefix);
+        };
+        for &op in ops.iter() {
+            if let Some(next) = op.apply(acc, x) {
+                prefix.push(op);
+                let keep_going = forward(&next, rest, ops, prefix, visit);
+                prefix.pop();
+                if !keep_going {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+    fn backward<'a, N: Value>(
+        lhs: &N,
+        rhs: &[N],
+        ops: &[&'a dyn Operator<N>],
+        suffix: &mut Vec<&'a dyn Operator<N>>,
+        visit: &mut dyn FnMut(&[&'a dyn Operator<N>]) -> bool,
+    ) -> bool {
+        let Some((last, rest)) = rhs.split_last() else {
+            return true;
+        };
+        if rest.is_empty() {
+            return lhs != last || visit(&suffix.iter().rev().copied().collect::<Vec<_>>());
+        }
+        // Evaluating the prefix forwards is cheaper than following a large preimage backwards.
+        let forward_cost = ops.len().saturating_pow(rest.len() as u32 - 1);
+        for &op in ops.iter() {
+            suffix.push(op);
+            let keep_going = match op.invert(lhs, last).filter(|p| p.len() <= forward_cost) {
+                Some(preimage) => preimage
+                    .iter()
+                    .all(|l| backward(l, rest, ops, suffix, visit)),
+                None => forward(
+                    &rest[0],
+                    &rest[1..],
+                    ops,
+                    &mut Vec::new(),
+                    &mut |acc, prefix| {
+                        op.apply(acc, last).as_ref() != Some(lhs) || {
+                            let sequence: Vec<_> =
+                                prefix.iter().chain(suffix.iter().rev()).copied().collect();
+                            visit(&sequence)
+                        }
+                    },
+                ),
+            };
+            suffix.pop();
+            if !keep_going {
+                return false;
+            }
+        }
+        true
+    }
+    if rhs.is_empty() {
+        return;
+    }
+    match order {
+        Order::LeftToRight => {
+            backward(lhs, rhs, ops, &mut Vec::new(), visit);
+        }
+        Order::Precedence => {
+            let sequences =
+                iter::repeat_n(ops.iter().copied(), rhs.len() - 1).multi_cartesian_product();
+            for sequence in sequences {
+                if evaluate(rhs, &sequence, order).as_ref() == Some(lhs) && !visit(&sequence) {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn solvable<N: Value>(lhs: &N, rhs: &[N], ops: &[&dyn Operator<N>], order: Order) -> bool {
+    let mut found = false;
+    search(lhs, rhs, ops, order, &mut |_| {
+        found = true;
+        false
+    });
+    found
+}
+
+fn count_solutions<N: Value>(lhs: &N, rhs: &[N], ops: &[&dyn Operator<N>], order: Order) -> usize {
+    let mut count = 0;
+    search(lhs, rhs, ops, order, &mut |_| {
+        count += 1;
+        true
+    });
+    count
+}
+
+// Up to `limit` operator sequences that make the equation hold.
+fn solutions<'a, N: Value>(
+    lhs: &N,
+    rhs: &[N],
+    ops: &[&'a dyn Operator<N>],
+    order: Order,
+    limit: usize,
+) -> Vec<Vec<&'a dyn Operator<N>>> {
+    let mut found = Vec::new();
+    if limit > 0 {
+        search(lhs, rhs, ops, order, &mut |sequence| {
+            found.push(sequence.to_vec());
+            found.len() < limit
+        });
+    }
+    found
+}
+
+fn render<N: Value>(rhs: &[N], ops: &[&dyn Operator<N>]) -> String {
+    let mut expr = rhs[0].to_string();
+    for (op, x) in ops.iter().zip(rhs.iter().skip(1)) {
+        expr.push_str(&format!(" {op} {x}"));
+    }
+    expr
+}
+
+#[allow(dead_code)]
 fn brute_force_solvable(lhs: usize, rhs: &[usize], op: Vec<Op>) -> bool {
     let n = rhs.len() - 1;
     let mut choices = Vec::new();
@@ -34,6 +453,7 @@ fn brute_force_solvable(lhs: usize, rhs: &[usize], op: Vec<Op>) -> bool {
     eval(choices, lhs, rhs)
 }
 
+#[allow(dead_code)]
 fn eval(choices: Vec<Vec<Op>>, lhs: usize, rhs: &[usize]) -> bool {
     choices
         .into_iter()
@@ -41,34 +461,183 @@ fn eval(choices: Vec<Vec<Op>>, lhs: usize, rhs: &[usize]) -> bool {
         .map(|ops| {
             let mut rhs = rhs.iter();
             let start = *rhs.next().unwrap();
-            let res = ops.iter().zip(rhs).fold(start, |acc, (op, x)| match op {
-                Op::Add => acc + x,
-                Op::Mul => acc * x,
-                Op::Concat => 10usize.pow(x.checked_ilog10().unwrap_or(0) + 1) * acc + x,
-            });
-            res
+            ops.iter()
+                .zip(rhs)
+                .try_fold(start, |acc, (op, x)| op.apply(&acc, x))
         })
-        .any(|res| res == lhs)
+        .any(|res| res == Some(lhs))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Timing {
+    index: usize,
+    solvable: bool,
+    elapsed: Duration,
+}
+
+// Decides every equation on all cores. Equations are handed out one at a time, since a few long
+// ones can take far longer than the rest. Timings come back in input order.
+fn check_all<N: Value>(
+    eqns: &[(N, Vec<N>)],
+    ops: &[&dyn Operator<N>],
+    progress: bool,
+) -> Vec<Timing> {
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let finished = AtomicBool::new(false);
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut timings: Vec<Timing> = thread::scope(|s| {
+        if progress {
+            s.spawn(|| show_progress(&done, eqns.len(), &finished));
+        }
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut timings = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((l, r)) = eqns.get(index) else {
+                            return timings;
+                        };
+                        let start = Instant::now();
+                        timings.push(Timing {
+                            index,
+                            solvable: solvable(l, r, ops, Order::LeftToRight),
+                            elapsed: start.elapsed(),
+                        });
+                        done.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+        // The progress bar has to stop before a worker's panic is passed on, or the scope would
+        // wait for it forever.
+        let results: Vec<_> = handles.into_iter().map(|h| h.join()).collect();
+        finished.store(true, Ordering::Relaxed);
+        results
+            .into_iter()
+            .flat_map(|r| r.unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect()
+    });
+    timings.sort_by_key(|t| t.index);
+    timings
+}
+
+// Redraws a bar on stderr until every equation is done or the workers have stopped.
+fn show_progress(done: &AtomicUsize, total: usize, finished: &AtomicBool) {
+    const WIDTH: usize = 40;
+    loop {
+        let n = done.load(Ordering::Relaxed).min(total);
+        let filled = (n * WIDTH).checked_div(total).unwrap_or(WIDTH);
+        eprint!(
+            "\r[{}{}] {n}/{total}",
+            "#".repeat(filled),
+            " ".repeat(WIDTH - filled)
+        );
+        if n == total || finished.load(Ordering::Relaxed) {
+            eprintln!();
+            return;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn slowest(timings: &[Timing], n: usize) -> Vec<Timing> {
+    let mut sorted = timings.to_vec();
+    sorted.sort_by_key(|t| std::cmp::Reverse(t.elapsed));
+    sorted.truncate(n);
+    sorted
+}
+
+// Sum of the targets of the equations the timings mark solvable.
+fn total<N: Value>(eqns: &[(N, Vec<N>)], timings: &[Timing]) -> N {
+    timings
+        .iter()
+        .filter(|t| t.solvable)
+        .fold(N::zero(), |sum, t| {
+            sum.try_add(&eqns[t.index].0)
+                .expect("Calibration result overflows")
+        })
+}
+
+// Sum of the targets of every equation that `ops` can satisfy.
+fn calibration<N: Value>(eqns: &[(N, Vec<N>)], ops: &[&dyn Operator<N>]) -> N {
+    total(eqns, &check_all(eqns, ops, false))
 }
 
 fn part1(eqns: &[(usize, Vec<usize>)]) -> usize {
-    eqns.iter()
-        .filter(|(l, r)| brute_force_solvable(*l, r, vec![Op::Add, Op::Mul]))
-        .map(|(l, _)| l)
-        .sum()
+    calibration(eqns, &PART1_OPS)
 }
 
 fn part2(eqns: &[(usize, Vec<usize>)]) -> usize {
-    eqns.iter()
-        .filter(|(l, r)| brute_force_solvable(*l, r, vec![Op::Add, Op::Mul, Op::Concat]))
-        .map(|(l, _)| l)
-        .sum()
+    calibration(eqns, &PART2_OPS)
+}
+
+fn explain<N: Value>(eqns: &[(N, Vec<N>)], args: &[String]) {
+    let custom: Vec<Op> = args
+        .iter()
+        .position(|a| a == "--ops")
+        .map(|k| {
+            args[k + 1]
+                .split(',')
+                .map(|s| s.parse().expect("Invalid operator"))
+                .collect()
+        })
+        .unwrap_or_else(|| vec![Op::Add, Op::Mul, Op::Concat]);
+    let ops: Vec<&dyn Operator<N>> = custom.iter().map(|op| op as &dyn Operator<N>).collect();
+    let order = if args.iter().any(|a| a == "--precedence") {
+        Order::Precedence
+    } else {
+        Order::LeftToRight
+    };
+    for (l, r) in eqns.iter() {
+        if let Some(witness) = solutions(l, r, &ops, order, 1).first() {
+            let count = count_solutions(l, r, &ops, order);
+            println!("{l} = {} ({count} solution(s))", render(r, witness));
+        }
+    }
+}
+
+// Part 2 is the slow one, so it is the part that reports progress and timings.
+fn report<N: Value>(eqns: &[(N, Vec<N>)], ops: &[&dyn Operator<N>], args: &[String]) {
+    let timings = check_all(eqns, ops, args.iter().any(|a| a == "--progress"));
+    println!("Part 2: {}", total(eqns, &timings));
+    if let Some(k) = args.iter().position(|a| a == "--timings") {
+        let n = args.get(k + 1).and_then(|s| s.parse().ok()).unwrap_or(10);
+        for t in slowest(&timings, n) {
+            let (l, r) = &eqns[t.index];
+            let verdict = if t.solvable { "solvable" } else { "unsolvable" };
+            println!(
+                "{:>12.3?}  {l}: {} ({verdict})",
+                t.elapsed,
+                r.iter().join(" ")
+            );
+        }
+    }
 }
 
 fn main() {
-    let eqns = load("input.txt");
-    println!("Part 1: {}", part1(&eqns));
-    println!("Part 2: {}", part2(&eqns));
+    let args: Vec<String> = env::args().collect();
+    // Targets beyond 64 bits need arbitrary precision; usize is much faster otherwise.
+    if args.iter().any(|a| a == "--bigint") {
+        let eqns: Vec<(BigUint, Vec<BigUint>)> = load("input.txt");
+        println!("Part 1: {}", calibration(&eqns, &[&Op::Add, &Op::Mul]));
+        report(&eqns, &[&Op::Add, &Op::Mul, &Op::Concat], &args);
+        if args.iter().any(|a| a == "--explain") {
+            explain(&eqns, &args);
+        }
+    } else {
+        let eqns = load("input.txt");
+        println!("Part 1: {}", part1(&eqns));
+        if args.iter().any(|a| a == "--progress" || a == "--timings") {
+            report(&eqns, &PART2_OPS, &args);
+        } else {
+            println!("Part 2: {}", part2(&eqns));
+        }
+        if args.iter().any(|a| a == "--explain") {
+            explain(&eqns, &args);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -86,4 +655,217 @@ mod test {
         let eqns = load("test.txt");
         assert_eq!(part2(&eqns), 11387);
     }
+
+    #[test]
+    fn test_solutions() {
+        let ops = PART1_OPS;
+        let ltr = Order::LeftToRight;
+        let expressions: Vec<String> = solutions(&3267, &[81, 40, 27], &ops, ltr, 10)
+            .iter()
+            .map(|s| render(&[81, 40, 27], s))
+            .collect();
+        assert_eq!(expressions, vec!["81 * 40 + 27", "81 + 40 * 27"]);
+        assert_eq!(count_solutions(&3267, &[81, 40, 27], &ops, ltr), 2);
+        assert_eq!(solutions(&3267, &[81, 40, 27], &ops, ltr, 1).len(), 1);
+        assert_eq!(
+            render(&[15, 6], &solutions(&156, &[15, 6], &PART2_OPS, ltr, 1)[0]),
+            "15 || 6"
+        );
+        assert_eq!(count_solutions(&0, &[1, 2, 3, 0], &ops, ltr), 4);
+        assert_eq!(solutions(&0, &[1, 2, 3, 0], &ops, ltr, 3).len(), 3);
+        for (l, r) in load("test.txt") {
+            assert_eq!(
+                solutions(&l, &r, &ops, ltr, usize::MAX).len(),
+                count_solutions(&l, &r, &ops, ltr)
+            );
+        }
+    }
+
+    #[test]
+    fn test_backward_search() {
+        let ops = PART2_OPS;
+        let ltr = Order::LeftToRight;
+        for (l, r) in load("test.txt").iter().chain(load("test_long.txt").iter()) {
+            assert_eq!(
+                solvable(l, r, &ops, ltr),
+                brute_force_solvable(*l, r, vec![Op::Add, Op::Mul, Op::Concat]),
+                "{l}: {r:?}"
+            );
+        }
+        assert!(solvable(&0, &[5, 0], &ops, ltr));
+        assert!(solvable(&50, &[5, 0], &ops, ltr));
+        assert!(!solvable(&1, &[5, 0], &ops, ltr));
+        assert!(solvable(&80, &[2; 40], &PART1_OPS, ltr));
+        assert!(!solvable(&81, &[2; 40], &PART1_OPS, ltr));
+    }
+
+    #[test]
+    fn test_check_all() {
+        let eqns = load("test.txt");
+        let timings = check_all(&eqns, &PART2_OPS, false);
+        assert_eq!(timings.len(), eqns.len());
+        for (t, (k, (l, r))) in timings.iter().zip(eqns.iter().enumerate()) {
+            assert_eq!(t.index, k);
+            assert_eq!(t.solvable, solvable(l, r, &PART2_OPS, Order::LeftToRight));
+        }
+        assert_eq!(total(&eqns, &timings), 11387);
+        let top = slowest(&timings, 3);
+        assert_eq!(top.len(), 3);
+        assert!(top.windows(2).all(|w| w[0].elapsed >= w[1].elapsed));
+        assert!(timings
+            .iter()
+            .all(|t| top.iter().any(|s| s.index == t.index) || t.elapsed <= top[2].elapsed));
+        assert!(check_all(&Vec::<(usize, Vec<usize>)>::new(), &PART1_OPS, true).is_empty());
+
+        struct Broken;
+        impl fmt::Display for Broken {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "broken")
+            }
+        }
+        impl Operator for Broken {
+            fn apply(&self, _acc: &usize, _x: &usize) -> Option<usize> {
+                panic!("Broken operator")
+            }
+        }
+        // A panicking worker must not leave the progress bar waiting for equations forever.
+        let result = panic::catch_unwind(|| check_all(&eqns, &[&Broken], true));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let ltr = Order::LeftToRight;
+        // 2^32 * 2^32 wraps to 0 and 10^20 * 1 + 2 past usize::MAX; neither may count as a match.
+        assert!(!solvable(&0, &[1 << 32, 1 << 32], &PART1_OPS, ltr));
+        assert!(!brute_force_solvable(0, &[1 << 32, 1 << 32], vec![Op::Mul]));
+        assert!(!solvable(&0, &[usize::MAX, 1], &PART1_OPS, ltr));
+        let long = [10_000_000_000, 10_000_000_000, 2];
+        assert_eq!(evaluate(&long, &[&Op::Concat, &Op::Concat], ltr), None);
+        assert!(!solvable(&2, &long, &PART2_OPS, ltr));
+        let big = |s: &str| s.parse::<BigUint>().unwrap();
+        let (l, r) = (
+            big("18446744073709551616"),
+            [big("4294967296"), big("4294967296")],
+        );
+        let ops: [&dyn Operator<BigUint>; 3] = [&Op::Add, &Op::Mul, &Op::Concat];
+        assert_eq!(
+            render(&r, &solutions(&l, &r, &ops, ltr, 1)[0]),
+            "4294967296 * 4294967296"
+        );
+        let r = [big("10000000000"), big("10000000000"), big("2")];
+        assert!(solvable(&big("1000000000010000000002"), &r, &ops, ltr));
+        assert_eq!(
+            count_solutions(
+                &big("64"),
+                &[big("2"), big("3"), big("2")],
+                &[&Op::Pow],
+                ltr
+            ),
+            1
+        );
+        // 2^4000000000 would need half a gigabyte, so it is pruned like any overflow.
+        let pow: [&dyn Operator<BigUint>; 1] = [&Op::Pow];
+        assert!(!solvable(
+            &big("1"),
+            &[big("2"), big("4000000000")],
+            &pow,
+            ltr
+        ));
+        assert!(solvable(
+            &big("1"),
+            &[big("1"), big("4000000000")],
+            &pow,
+            ltr
+        ));
+        let l = BigUint::from(1u8) << 1000;
+        assert!(solvable(&l, &[big("2"), big("1000")], &pow, ltr));
+        for (l, r) in load::<BigUint>("test.txt") {
+            let small: Vec<usize> = r.iter().map(|x| x.to_usize().unwrap()).collect();
+            assert_eq!(
+                solvable(&l, &r, &ops, ltr),
+                solvable(&l.to_usize().unwrap(), &small, &PART2_OPS, ltr)
+            );
+        }
+    }
+
+    struct Max;
+
+    impl fmt::Display for Max {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "max")
+        }
+    }
+
+    impl Operator for Max {
+        fn apply(&self, acc: &usize, x: &usize) -> Option<usize> {
+            Some(*acc.max(x))
+        }
+    }
+
+    #[test]
+    fn test_operators() {
+        let all = [Op::Add, Op::Mul, Op::Concat, Op::Sub, Op::Div, Op::Pow];
+        let ops: Vec<&dyn Operator> = all.iter().map(|op| op as &dyn Operator).collect();
+        // Exponents past u32 as well, which only 0 and 1 survive.
+        let huge = 5_000_000_000;
+        let extra = [
+            (1, vec![1, huge]),
+            (0, vec![0, huge]),
+            (2, vec![2, huge]),
+            (1, vec![3, 2, huge]),
+            (1, vec![2, 1, huge]),
+        ];
+        for (l, r) in load("test.txt").into_iter().chain(extra) {
+            let mut backward = solutions(&l, &r, &ops, Order::LeftToRight, usize::MAX)
+                .iter()
+                .map(|s| render(&r, s))
+                .collect::<Vec<_>>();
+            let mut brute = iter::repeat_n(ops.iter().copied(), r.len() - 1)
+                .multi_cartesian_product()
+                .filter(|s| evaluate(&r, s, Order::LeftToRight) == Some(l))
+                .map(|s| render(&r, &s))
+                .collect::<Vec<_>>();
+            backward.sort();
+            brute.sort();
+            assert_eq!(backward, brute);
+        }
+        assert!(solvable(&1, &[1, huge], &[&Op::Pow], Order::LeftToRight));
+        assert!(!solvable(&1, &[2, huge], &[&Op::Pow], Order::LeftToRight));
+        assert_eq!(
+            render(
+                &[17, 5, 3],
+                &solutions(&4, &[17, 5, 3], &ops, Order::LeftToRight, 1)[0]
+            ),
+            "17 - 5 / 3"
+        );
+        assert!(solvable(&64, &[2, 3, 2], &ops, Order::LeftToRight));
+        assert_eq!(
+            evaluate(&[2, 3, 2], &[&Op::Pow, &Op::Pow], Order::Precedence),
+            Some(512)
+        );
+        assert_eq!(
+            evaluate(&[8, 2, 2], &[&Op::Div, &Op::Div], Order::Precedence),
+            Some(2)
+        );
+        assert_eq!(evaluate(&[5, 6], &[&Op::Sub], Order::Precedence), None);
+        assert!(solvable(&14, &[2, 3, 4], &PART1_OPS, Order::Precedence));
+        assert!(!solvable(&14, &[2, 3, 4], &PART1_OPS, Order::LeftToRight));
+        for order in [Order::LeftToRight, Order::Precedence] {
+            assert_eq!(count_solutions(&5, &[5], &PART1_OPS, order), 1);
+            assert_eq!(count_solutions(&4, &[5], &PART1_OPS, order), 0);
+        }
+        let custom: [&dyn Operator; 2] = [&Max, &Op::Add];
+        assert_eq!(
+            count_solutions(&9, &[3, 7, 2], &custom, Order::LeftToRight),
+            1
+        );
+        assert_eq!(
+            render(
+                &[3, 7, 2],
+                &solutions(&9, &[3, 7, 2], &custom, Order::LeftToRight, 1)[0]
+            ),
+            "3 max 7 + 2"
+        );
+    }
 }
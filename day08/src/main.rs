@@ -1,130 +1,580 @@
 use itertools::Itertools;
 use std::{
-    collections::{HashMap, HashSet},
-    fs,
+    collections::{BTreeMap, HashMap, HashSet},
+    env,
+    fs::{self, File},
+    io::{self, BufWriter},
     mem::swap,
+    str::FromStr,
 };
 
+// Which points along the line through an antenna pair resonate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Harmonics {
+    // Harmonic k puts an antinode k separations from each antenna, on the side of its partner:
+    // 1 is the partner itself and 2 is the point twice as far from one antenna as the other.
+    Multiples(Vec<isize>),
+    // Every lattice point on the line.
+    Unlimited,
+}
+
+impl FromStr for Harmonics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "all" {
+            return Ok(Self::Unlimited);
+        }
+        s.split(',')
+            .map(|k| k.parse().map_err(|_| format!("Invalid harmonic {k}")))
+            .collect::<Result<_, _>>()
+            .map(Self::Multiples)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Resonance {
+    harmonics: Harmonics,
+    // Whether antennas of different frequencies also resonate with each other.
+    cross_frequency: bool,
+}
+
+impl Resonance {
+    fn part1() -> Self {
+        Self {
+            harmonics: Harmonics::Multiples(vec![2]),
+            cross_frequency: false,
+        }
+    }
+    fn part2() -> Self {
+        Self {
+            harmonics: Harmonics::Unlimited,
+            cross_frequency: false,
+        }
+    }
+}
+
+// A rectangle of lattice points, inclusive on both ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Bounds {
+    min: (isize, isize),
+    max: (isize, isize),
+}
+
+impl Bounds {
+    fn contains(&self, (x, y): (isize, isize)) -> bool {
+        x >= self.min.0 && x <= self.max.0 && y >= self.min.1 && y <= self.max.1
+    }
+
+    // The range of t for which `start + t * step` lies inside, if there is one.
+    fn steps(&self, start: (isize, isize), step: (isize, isize)) -> Option<(i128, i128)> {
+        let (mut lo, mut hi) = (i128::MIN, i128::MAX);
+        for (a, d, min, max) in [
+            (start.0, step.0, self.min.0, self.max.0),
+            (start.1, step.1, self.min.1, self.max.1),
+        ] {
+            let (a, d, min, max) = (a as i128, d as i128, min as i128, max as i128);
+            if d == 0 {
+                if a < min || a > max {
+                    return None;
+                }
+                continue;
+            }
+            let (from, to) = if d > 0 {
+                (min - a, max - a)
+            } else {
+                (a - max, a - min)
+            };
+            lo = lo.max(-(-from).div_euclid(d.abs()));
+            hi = hi.min(to.div_euclid(d.abs()));
+        }
+        (lo <= hi).then_some((lo, hi))
+    }
+}
+
+impl FromStr for Bounds {
+    type Err = String;
+
+    // `x0,y0,x1,y1`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coords: Vec<isize> = s
+            .split(',')
+            .map(|c| c.parse().map_err(|_| format!("Invalid coordinate {c}")))
+            .collect::<Result<_, _>>()?;
+        match coords[..] {
+            [x0, y0, x1, y1] => Ok(Self {
+                min: (x0.min(x1), y0.min(y1)),
+                max: (x0.max(x1), y0.max(y1)),
+            }),
+            _ => Err(format!("Expected x0,y0,x1,y1, got {s}")),
+        }
+    }
+}
+
+// The line through two distinct lattice points, as its smallest lattice step and the value of
+// `dy * x - dx * y`, which is the same at every point on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Line {
+    step: (isize, isize),
+    offset: i128,
+}
+
+impl Line {
+    fn through((x1, y1): (isize, isize), (x2, y2): (isize, isize)) -> Option<Self> {
+        let (dx, dy) = (x2 - x1, y2 - y1);
+        let m = gcd(dx.unsigned_abs(), dy.unsigned_abs()) as isize;
+        if m == 0 {
+            return None;
+        }
+        let sign = if dx < 0 || dx == 0 && dy < 0 { -1 } else { 1 };
+        let step = (sign * dx / m, sign * dy / m);
+        Some(Self {
+            step,
+            offset: step.1 as i128 * x1 as i128 - step.0 as i128 * y1 as i128,
+        })
+    }
+
+    fn contains(&self, (x, y): (isize, isize)) -> bool {
+        self.step.1 as i128 * x as i128 - self.step.0 as i128 * y as i128 == self.offset
+    }
+
+    // The lattice point where two lines cross, if they cross at one.
+    fn intersection(&self, other: &Line) -> Option<(isize, isize)> {
+        let (dx1, dy1) = (self.step.0 as i128, self.step.1 as i128);
+        let (dx2, dy2) = (other.step.0 as i128, other.step.1 as i128);
+        let det = dx1 * dy2 - dy1 * dx2;
+        if det == 0 {
+            return None;
+        }
+        let x = dx1 * other.offset - dx2 * self.offset;
+        let y = dy1 * other.offset - dy2 * self.offset;
+        if x % det != 0 || y % det != 0 {
+            return None;
+        }
+        Some(((x / det).try_into().ok()?, (y / det).try_into().ok()?))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FrequencyStats {
+    frequency: char,
+    antennas: usize,
+    antinodes: usize,
+    // Antinodes that some other frequency produces as well.
+    shared: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Colour(u8, u8, u8);
+
+const BACKGROUND: Colour = Colour(24, 24, 32);
+
+impl Colour {
+    // The k-th of n hues spaced evenly around the colour wheel, at full saturation.
+    fn spread(k: usize, n: usize) -> Self {
+        let hue = 6.0 * k as f64 / n.max(1) as f64;
+        let rising = (255.0 * hue.fract()) as u8;
+        let falling = 255 - rising;
+        match hue as usize {
+            0 => Colour(255, rising, 0),
+            1 => Colour(falling, 255, 0),
+            2 => Colour(0, 255, rising),
+            3 => Colour(0, falling, 255),
+            4 => Colour(rising, 0, 255),
+            _ => Colour(255, 0, falling),
+        }
+    }
+    fn dim(self) -> Self {
+        Colour(self.0 / 2, self.1 / 2, self.2 / 2)
+    }
+    fn hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+fn escape_xml(c: char) -> String {
+    match c {
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        '&' => "&amp;".to_string(),
+        '"' => "&quot;".to_string(),
+        '\'' => "&apos;".to_string(),
+        _ => c.to_string(),
+    }
+}
+
 struct Grid {
     antennae: HashMap<char, HashSet<(isize, isize)>>,
-    antinodes_pt1: HashSet<(isize, isize)>,
-    antinodes_pt2: HashSet<(isize, isize)>,
-    width: isize,
-    height: isize,
+    bounds: Bounds,
 }
 
 impl Grid {
-    fn in_grid(&self, x: isize, y: isize) -> bool {
-        x >= 0 && x < self.width && y >= 0 && y < self.height
-    }
-    fn find_antinodes_pt1(&mut self) {
-        for antennae in self.antennae.values() {
-            for pair in antennae.iter().combinations(2) {
-                let (x1, y1) = pair[0];
-                let (x2, y2) = pair[1];
-                let nx1 = 2 * x2 - x1;
-                let ny1 = 2 * y2 - y1;
-                let nx2 = 2 * x1 - x2;
-                let ny2 = 2 * y1 - y2;
-                if self.in_grid(nx1, ny1) {
-                    self.antinodes_pt1.insert((nx1, ny1));
+    fn pairs(&self, cross_frequency: bool) -> Vec<((isize, isize), (isize, isize))> {
+        if cross_frequency {
+            self.antennae
+                .values()
+                .flatten()
+                .copied()
+                .tuple_combinations()
+                .collect()
+        } else {
+            self.antennae
+                .values()
+                .flat_map(|antennae| antennae.iter().copied().tuple_combinations())
+                .collect()
+        }
+    }
+
+    fn antinodes(&self, resonance: &Resonance) -> HashSet<(isize, isize)> {
+        let mut antinodes = HashSet::new();
+        for ((x1, y1), (x2, y2)) in self.pairs(resonance.cross_frequency) {
+            match &resonance.harmonics {
+                Harmonics::Multiples(multiples) => {
+                    let (dx, dy) = (x2 - x1, y2 - y1);
+                    for k in multiples {
+                        for p in [(x1 + k * dx, y1 + k * dy), (x2 - k * dx, y2 - k * dy)] {
+                            if self.bounds.contains(p) {
+                                antinodes.insert(p);
+                            }
+                        }
+                    }
+                }
+                Harmonics::Unlimited => match Line::through((x1, y1), (x2, y2)) {
+                    Some(line) => {
+                        let (dx, dy) = line.step;
+                        if let Some((lo, hi)) = self.bounds.steps((x1, y1), line.step) {
+                            for t in lo as isize..=hi as isize {
+                                antinodes.insert((x1 + t * dx, y1 + t * dy));
+                            }
+                        }
+                    }
+                    // Coincident antennas span no line, only their shared position.
+                    None => {
+                        if self.bounds.contains((x1, y1)) {
+                            antinodes.insert((x1, y1));
+                        }
+                    }
+                },
+            }
+        }
+        antinodes
+    }
+
+    // Same as the size of `antinodes`, but without visiting every point on a resonant line, so
+    // it copes with huge bounds. Each distinct line contributes the lattice points it has inside
+    // the bounds, less one for every other line already counted at the same crossing.
+    fn count_antinodes(&self, resonance: &Resonance) -> usize {
+        if resonance.harmonics != Harmonics::Unlimited {
+            return self.antinodes(resonance).len();
+        }
+        let mut lines = HashMap::new();
+        let mut points = HashSet::new();
+        for (a, b) in self.pairs(resonance.cross_frequency) {
+            match Line::through(a, b) {
+                Some(line) => {
+                    lines.entry(line).or_insert(a);
                 }
-                if self.in_grid(nx2, ny2) {
-                    self.antinodes_pt1.insert((nx2, ny2));
+                None if self.bounds.contains(a) => {
+                    points.insert(a);
                 }
+                None => {}
             }
         }
+        let lines: Vec<(Line, (isize, isize))> = lines.into_iter().collect();
+        let on_lines: usize = lines
+            .iter()
+            .filter_map(|(line, start)| self.bounds.steps(*start, line.step))
+            .map(|(lo, hi)| (hi - lo + 1) as usize)
+            .sum();
+        let mut crossings: HashMap<(isize, isize), HashSet<usize>> = HashMap::new();
+        for ((i, (l1, _)), (j, (l2, _))) in lines.iter().enumerate().tuple_combinations() {
+            if let Some(p) = l1.intersection(l2).filter(|&p| self.bounds.contains(p)) {
+                crossings.entry(p).or_default().extend([i, j]);
+            }
+        }
+        let repeats: usize = crossings.values().map(|through| through.len() - 1).sum();
+        let isolated = points
+            .iter()
+            .filter(|&&p| !lines.iter().any(|(line, _)| line.contains(p)))
+            .count();
+        on_lines - repeats + isolated
+    }
+    fn antinodes_by_frequency(
+        &self,
+        resonance: &Resonance,
+    ) -> BTreeMap<char, HashSet<(isize, isize)>> {
+        let alone = Resonance {
+            cross_frequency: false,
+            ..resonance.clone()
+        };
+        self.antennae
+            .iter()
+            .map(|(&frequency, antennae)| {
+                let grid = Grid {
+                    antennae: HashMap::from([(frequency, antennae.clone())]),
+                    bounds: self.bounds,
+                };
+                (frequency, grid.antinodes(&alone))
+            })
+            .collect()
     }
 
-    fn find_antinodes_pt2(&mut self) {
-        for antennae in self.antennae.values() {
-            for pair in antennae.iter().combinations(2) {
-                let (x1, y1) = *pair[0];
-                let (x2, y2) = *pair[1];
-                let mut dx = x2 - x1;
-                let mut dy = y2 - y1;
-                let m = gcd(dx.unsigned_abs(), dy.unsigned_abs()) as isize;
-                dx /= m;
-                dy /= m;
-                let mut px = x1;
-                let mut py = y1;
-                let mut qx = x1;
-                let mut qy = y1;
-                while self.in_grid(px, py) || self.in_grid(qx, qy) {
-                    if self.in_grid(px, py) {
-                        self.antinodes_pt2.insert((px, py));
-                    }
-                    if self.in_grid(qx, qy) {
-                        self.antinodes_pt2.insert((qx, qy));
-                    }
-                    px -= dx;
-                    py -= dy;
-                    qx += dx;
-                    qy += dy;
+    // Frequencies interact only within themselves here, so the breakdown ignores
+    // `cross_frequency`.
+    fn stats(&self, resonance: &Resonance) -> Vec<FrequencyStats> {
+        let by_frequency = self.antinodes_by_frequency(resonance);
+        by_frequency
+            .iter()
+            .map(|(&frequency, antinodes)| FrequencyStats {
+                frequency,
+                antennas: self.antennae[&frequency].len(),
+                antinodes: antinodes.len(),
+                shared: antinodes
+                    .iter()
+                    .filter(|p| {
+                        by_frequency
+                            .iter()
+                            .any(|(&other, theirs)| other != frequency && theirs.contains(p))
+                    })
+                    .count(),
+            })
+            .collect()
+    }
+
+    fn antenna_at(&self, p: (isize, isize)) -> Option<char> {
+        self.antennae
+            .iter()
+            .find(|(_, antennae)| antennae.contains(&p))
+            .map(|(&frequency, _)| frequency)
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (isize, isize)> + '_ {
+        (self.bounds.min.1..=self.bounds.max.1)
+            .flat_map(|y| (self.bounds.min.0..=self.bounds.max.0).map(move |x| (x, y)))
+    }
+
+    // Antennas show their frequency, other antinodes '#'.
+    fn render_text(&self, antinodes: &HashSet<(isize, isize)>) -> String {
+        let width = (self.bounds.max.0 - self.bounds.min.0 + 1) as usize;
+        self.cells()
+            .map(|p| match self.antenna_at(p) {
+                Some(frequency) => frequency,
+                None if antinodes.contains(&p) => '#',
+                None => '.',
+            })
+            .chunks(width)
+            .into_iter()
+            .map(|row| row.collect::<String>())
+            .join("\n")
+    }
+
+    // Each frequency gets its own colour; an antinode shared by several is drawn white.
+    fn cell_colours(
+        &self,
+        by_frequency: &BTreeMap<char, HashSet<(isize, isize)>>,
+    ) -> Vec<((isize, isize), Colour, Option<char>)> {
+        let palette: HashMap<char, Colour> = by_frequency
+            .keys()
+            .enumerate()
+            .map(|(k, &frequency)| (frequency, Colour::spread(k, by_frequency.len())))
+            .collect();
+        self.cells()
+            .filter_map(|p| {
+                if let Some(frequency) = self.antenna_at(p) {
+                    return Some((p, palette[&frequency], Some(frequency)));
                 }
+                let mut sources = by_frequency.iter().filter(|(_, a)| a.contains(&p));
+                match (sources.next(), sources.next()) {
+                    (Some((frequency, _)), None) => Some((p, palette[frequency].dim(), None)),
+                    (Some(_), Some(_)) => Some((p, Colour(200, 200, 200), None)),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    fn render_svg(&self, by_frequency: &BTreeMap<char, HashSet<(isize, isize)>>) -> String {
+        const CELL: isize = 12;
+        let (width, height) = (
+            self.bounds.max.0 - self.bounds.min.0 + 1,
+            self.bounds.max.1 - self.bounds.min.1 + 1,
+        );
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+            width * CELL,
+            height * CELL,
+            BACKGROUND.hex()
+        );
+        for ((x, y), colour, antenna) in self.cell_colours(by_frequency) {
+            let (x, y) = (
+                (x - self.bounds.min.0) * CELL,
+                (y - self.bounds.min.1) * CELL,
+            );
+            match antenna {
+                Some(frequency) => svg.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" font-size=\"{CELL}\" font-family=\"monospace\" \
+                     text-anchor=\"middle\" fill=\"{}\">{}</text>\n",
+                    x + CELL / 2,
+                    y + CELL - 2,
+                    colour.hex(),
+                    escape_xml(frequency)
+                )),
+                None => svg.push_str(&format!(
+                    "<rect x=\"{x}\" y=\"{y}\" width=\"{CELL}\" height=\"{CELL}\" fill=\"{}\"/>\n",
+                    colour.hex()
+                )),
             }
         }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    // Antennas are solid squares and antinodes smaller, dimmer ones.
+    fn write_png(
+        &self,
+        by_frequency: &BTreeMap<char, HashSet<(isize, isize)>>,
+        path: &str,
+    ) -> io::Result<()> {
+        const CELL: usize = 6;
+        let width = (self.bounds.max.0 - self.bounds.min.0 + 1) as usize * CELL;
+        let height = (self.bounds.max.1 - self.bounds.min.1 + 1) as usize * CELL;
+        let mut pixels = [BACKGROUND.0, BACKGROUND.1, BACKGROUND.2].repeat(width * height);
+        for ((x, y), colour, antenna) in self.cell_colours(by_frequency) {
+            let (x, y) = (
+                (x - self.bounds.min.0) as usize * CELL,
+                (y - self.bounds.min.1) as usize * CELL,
+            );
+            let inset = if antenna.is_some() { 0 } else { 1 };
+            for py in y + inset..y + CELL - inset {
+                for px in x + inset..x + CELL - inset {
+                    let k = (py * width + px) * 3;
+                    pixels[k..k + 3].copy_from_slice(&[colour.0, colour.1, colour.2]);
+                }
+            }
+        }
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&pixels)?;
+        Ok(())
     }
 }
 
+// gcd(n, 0) is n, so a horizontal or vertical separation reduces to a unit step.
 fn gcd(mut n: usize, mut m: usize) -> usize {
-    assert!(n != 0 && m != 0);
     while m != 0 {
-        if m < n {
-            swap(&mut m, &mut n);
-        }
-        m %= n;
+        n %= m;
+        swap(&mut m, &mut n);
     }
     n
 }
 
-fn load(path: &str) -> Grid {
+// Any character other than '.' is an antenna, and the character is its frequency.
+fn parse(raw: &str) -> Grid {
     let mut width: isize = 0;
     let mut height = 0;
     let mut antennae = HashMap::new();
-    let antinodes_pt1 = HashSet::new();
-    let antinodes_pt2 = HashSet::new();
-    fs::read_to_string(path)
-        .expect("File not found")
-        .split('\n')
-        .enumerate()
-        .for_each(|(y, r)| {
-            height += 1;
-            width = r.len() as isize;
-            r.chars().enumerate().for_each(|(x, c)| {
-                if c.is_alphanumeric() {
-                    antennae
-                        .entry(c)
-                        .or_insert(HashSet::new())
-                        .insert((x as isize, y as isize));
-                } else {
-                    assert_eq!(c, '.');
-                }
-            });
+    raw.split('\n').enumerate().for_each(|(y, r)| {
+        height += 1;
+        width = r.chars().count() as isize;
+        r.chars().enumerate().for_each(|(x, c)| {
+            if c != '.' {
+                antennae
+                    .entry(c)
+                    .or_insert(HashSet::new())
+                    .insert((x as isize, y as isize));
+            }
         });
+    });
     Grid {
         antennae,
-        antinodes_pt1,
-        antinodes_pt2,
-        width,
-        height,
+        bounds: Bounds {
+            min: (0, 0),
+            max: (width - 1, height - 1),
+        },
     }
 }
 
-fn part1(grid: &mut Grid) -> usize {
-    grid.find_antinodes_pt1();
-    grid.antinodes_pt1.len()
+// One antenna per line, as its frequency and coordinates: `A 1000000 -52`.
+fn parse_survey(raw: &str, bounds: Bounds) -> Grid {
+    let mut antennae: HashMap<char, HashSet<(isize, isize)>> = HashMap::new();
+    for line in raw.lines().filter(|l| !l.trim().is_empty()) {
+        let (frequency, x, y) = line
+            .split_whitespace()
+            .collect_tuple()
+            .expect("Expected a frequency and two coordinates");
+        let frequency = frequency
+            .chars()
+            .exactly_one()
+            .expect("Expected a single-character frequency");
+        let coordinate = |s: &str| s.parse().expect("Expected an integer coordinate");
+        antennae
+            .entry(frequency)
+            .or_default()
+            .insert((coordinate(x), coordinate(y)));
+    }
+    Grid { antennae, bounds }
+}
+
+fn load(path: &str) -> Grid {
+    parse(&fs::read_to_string(path).expect("File not found"))
 }
 
-fn part2(grid: &mut Grid) -> usize {
-    grid.find_antinodes_pt2();
-    grid.antinodes_pt2.len()
+fn part1(grid: &Grid) -> usize {
+    grid.antinodes(&Resonance::part1()).len()
+}
+
+fn part2(grid: &Grid) -> usize {
+    grid.antinodes(&Resonance::part2()).len()
 }
 
 fn main() {
-    let mut grid = load("input.txt");
-    println!("Part 1: {}", part1(&mut grid));
-    println!("Part 2: {}", part2(&mut grid));
+    let grid = load("input.txt");
+    println!("Part 1: {}", part1(&grid));
+    println!("Part 2: {}", part2(&grid));
+    let args: Vec<String> = env::args().collect();
+    let resonance = Resonance {
+        harmonics: match args.iter().position(|a| a == "--harmonics") {
+            Some(k) => args[k + 1].parse().expect("Invalid harmonics"),
+            None => Harmonics::Unlimited,
+        },
+        cross_frequency: args.iter().any(|a| a == "--cross"),
+    };
+    if let Some(k) = args.iter().position(|a| a == "--survey") {
+        let bounds = args[k + 2].parse().expect("Invalid bounds");
+        let raw = fs::read_to_string(&args[k + 1]).expect("File not found");
+        let survey = parse_survey(&raw, bounds);
+        println!("Survey: {}", survey.count_antinodes(&resonance));
+    } else if args.iter().any(|a| a == "--harmonics") {
+        println!("Custom: {}", grid.count_antinodes(&resonance));
+    }
+    if args.iter().any(|a| a == "--stats") {
+        println!("freq  antennas  antinodes  shared");
+        for s in grid.stats(&resonance) {
+            println!(
+                "{:>4}  {:>8}  {:>9}  {:>6}",
+                s.frequency, s.antennas, s.antinodes, s.shared
+            );
+        }
+    }
+    if let Some(k) = args.iter().position(|a| a == "--map") {
+        let by_frequency = grid.antinodes_by_frequency(&resonance);
+        let path = args.get(k + 2);
+        match (args[k + 1].as_str(), path) {
+            ("text", None) => println!("{}", grid.render_text(&grid.antinodes(&resonance))),
+            ("text", Some(path)) => fs::write(path, grid.render_text(&grid.antinodes(&resonance)))
+                .expect("Unable to write map"),
+            ("svg", Some(path)) => {
+                fs::write(path, grid.render_svg(&by_frequency)).expect("Unable to write map")
+            }
+            ("png", Some(path)) => grid
+                .write_png(&by_frequency, path)
+                .expect("Unable to write map"),
+            (format, _) => panic!("Unknown map format {format}, or missing path"),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -133,13 +583,238 @@ mod test {
 
     #[test]
     fn test_part1() {
-        let mut grid = load("test.txt");
-        assert_eq!(part1(&mut grid), 14);
+        let grid = load("test.txt");
+        assert_eq!(part1(&grid), 14);
     }
 
     #[test]
     fn test_part2() {
-        let mut grid = load("test.txt");
-        assert_eq!(part2(&mut grid), 34);
+        let grid = load("test.txt");
+        assert_eq!(part2(&grid), 34);
+    }
+
+    #[test]
+    fn test_resonance() {
+        let grid = load("test.txt");
+        let rules = |harmonics: &str, cross_frequency| Resonance {
+            harmonics: harmonics.parse().unwrap(),
+            cross_frequency,
+        };
+        assert_eq!(rules("2", false), Resonance::part1());
+        assert_eq!(rules("all", false), Resonance::part2());
+        assert!("2,x".parse::<Harmonics>().is_err());
+        // Harmonic 1 lands on the partner antenna, so it marks every antenna with a partner.
+        assert_eq!(grid.antinodes(&rules("1", false)).len(), 7);
+        let both = grid.antinodes(&rules("1,2", false));
+        assert!(grid.antinodes(&rules("1", false)).is_subset(&both));
+        assert!(grid.antinodes(&rules("2", false)).is_subset(&both));
+        assert!(both.is_subset(&grid.antinodes(&Resonance::part2())));
+        assert!(grid
+            .antinodes(&rules("2", false))
+            .is_subset(&grid.antinodes(&rules("2", true))));
+        // An A and a 0 one step apart on a diagonal resonate only across frequencies.
+        let mut pair = load("test.txt");
+        pair.antennae = HashMap::from([
+            ('A', HashSet::from([(1, 1)])),
+            ('0', HashSet::from([(2, 2)])),
+        ]);
+        assert!(pair.antinodes(&rules("2", false)).is_empty());
+        assert_eq!(
+            pair.antinodes(&rules("2", true)),
+            HashSet::from([(0, 0), (3, 3)])
+        );
+        assert_eq!(pair.antinodes(&rules("all", true)).len(), 12);
+    }
+
+    #[test]
+    fn test_gcd() {
+        assert_eq!(gcd(12, 18), 6);
+        assert_eq!(gcd(7, 0), 7);
+        assert_eq!(gcd(0, 7), 7);
+        assert_eq!(gcd(0, 0), 0);
+    }
+
+    #[test]
+    fn test_degenerate_pairs() {
+        let grid = parse("a..a.\n.....\n→....\n.....\n→....");
+        assert_eq!(part2(&grid), 9);
+        assert_eq!(grid.antinodes(&Resonance::part1()), HashSet::from([(0, 0)]));
+        let mut coincident = parse(".....\n.....");
+        coincident.antennae = HashMap::from([
+            ('x', HashSet::from([(1, 1)])),
+            ('y', HashSet::from([(1, 1)])),
+        ]);
+        let cross = |harmonics| Resonance {
+            harmonics,
+            cross_frequency: true,
+        };
+        assert_eq!(
+            coincident.antinodes(&cross(Harmonics::Unlimited)),
+            HashSet::from([(1, 1)])
+        );
+        assert_eq!(
+            coincident.antinodes(&cross(Harmonics::Multiples(vec![2]))),
+            HashSet::from([(1, 1)])
+        );
+    }
+
+    fn generate(width: usize, height: usize, seed: u64) -> String {
+        const SYMBOLS: [char; 8] = ['a', 'Z', '7', '#', '@', 'é', 'λ', '→'];
+        let mut state = seed;
+        let mut rows = Vec::new();
+        for _ in 0..height {
+            let row: String = (0..width)
+                .map(|_| {
+                    state = state
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    if state >> 33 < (1 << 31) / 8 {
+                        SYMBOLS[(state >> 20) as usize % SYMBOLS.len()]
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            rows.push(row);
+        }
+        rows.join("\n")
+    }
+
+    // Checks every cell against every same-frequency pair: on the line through both, and for
+    // part 1 also beyond the pair and twice as far from one antenna as from the other.
+    fn brute_force(raw: &str, ratio_only: bool) -> HashSet<(isize, isize)> {
+        let grid: Vec<Vec<char>> = raw.split('\n').map(|r| r.chars().collect()).collect();
+        let cells: Vec<(isize, isize)> = (0..grid.len() as isize)
+            .flat_map(|y| (0..grid[0].len() as isize).map(move |x| (x, y)))
+            .collect();
+        let at = |(x, y): (isize, isize)| grid[y as usize][x as usize];
+        let antennae: Vec<_> = cells.iter().copied().filter(|&p| at(p) != '.').collect();
+        let dist = |(x1, y1): (isize, isize), (x2, y2): (isize, isize)| {
+            (x1 - x2).pow(2) + (y1 - y2).pow(2)
+        };
+        cells
+            .iter()
+            .copied()
+            .filter(|&(x, y)| {
+                antennae.iter().tuple_combinations().any(|(&a, &b)| {
+                    let collinear = (b.0 - a.0) * (y - a.1) == (b.1 - a.1) * (x - a.0);
+                    let (da, db) = (dist((x, y), a), dist((x, y), b));
+                    let outside = (x - a.0) * (x - b.0) + (y - a.1) * (y - b.1) > 0;
+                    at(a) == at(b)
+                        && collinear
+                        && (!ratio_only || outside && (da == 4 * db || db == 4 * da))
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_generated_fields() {
+        for seed in 0..10 {
+            let raw = generate(17, 11, seed);
+            let grid = parse(&raw);
+            assert_eq!(
+                grid.antinodes(&Resonance::part1()),
+                brute_force(&raw, true),
+                "{raw}"
+            );
+            assert_eq!(
+                grid.antinodes(&Resonance::part2()),
+                brute_force(&raw, false),
+                "{raw}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_survey() {
+        let raw = "A 1000000 -52\nA 2000000 -52\nB 0 0\nB 3 3\nC 5 7\n";
+        let bounds: Bounds = "-10000000,-10000000,10000000,10000000".parse().unwrap();
+        let survey = parse_survey(raw, bounds);
+        // y = -52 and y = x, each 20000001 points across, crossing once at (-52, -52).
+        assert_eq!(survey.count_antinodes(&Resonance::part2()), 40_000_001);
+        assert_eq!(
+            survey.count_antinodes(&Resonance::part1()),
+            survey.antinodes(&Resonance::part1()).len()
+        );
+        assert_eq!(survey.count_antinodes(&Resonance::part1()), 4);
+        // Antennas outside the bounds still resonate inside them.
+        let far = parse_survey(
+            "a -100 -100\na -99 -99\nb 3 -50\nb 3 50\nc 2 2\nd 2 2",
+            bounds,
+        );
+        let small = Grid {
+            antennae: far.antennae.clone(),
+            bounds: "0,0,9,4".parse().unwrap(),
+        };
+        for cross_frequency in [false, true] {
+            let resonance = Resonance {
+                harmonics: Harmonics::Unlimited,
+                cross_frequency,
+            };
+            assert_eq!(
+                small.count_antinodes(&resonance),
+                small.antinodes(&resonance).len()
+            );
+        }
+        assert_eq!(small.count_antinodes(&Resonance::part2()), 5 + 5 - 1);
+        assert!("1,2,3".parse::<Bounds>().is_err());
+    }
+
+    #[test]
+    fn test_count_antinodes() {
+        let grid = load("test.txt");
+        assert_eq!(grid.count_antinodes(&Resonance::part2()), 34);
+        let cross = Resonance {
+            harmonics: Harmonics::Unlimited,
+            cross_frequency: true,
+        };
+        assert_eq!(grid.count_antinodes(&cross), grid.antinodes(&cross).len());
+    }
+
+    #[test]
+    fn test_stats_and_maps() {
+        let grid = load("test.txt");
+        let expected = "......#....#\n\
+                        ...#....0...\n\
+                        ....#0....#.\n\
+                        ..#....0....\n\
+                        ....0....#..\n\
+                        .#....A.....\n\
+                        ...#........\n\
+                        #......#....\n\
+                        ........A...\n\
+                        .........A..\n\
+                        ..........#.\n\
+                        ..........#.";
+        assert_eq!(
+            grid.render_text(&grid.antinodes(&Resonance::part1())),
+            expected
+        );
+        let stats = grid.stats(&Resonance::part1());
+        assert_eq!(
+            stats
+                .iter()
+                .map(|s| (s.frequency, s.antennas))
+                .collect::<Vec<_>>(),
+            vec![('0', 4), ('A', 3)]
+        );
+        let sum: usize = stats.iter().map(|s| s.antinodes).sum();
+        assert_eq!(sum - stats[0].shared, part1(&grid));
+        assert_eq!(stats[0].shared, stats[1].shared);
+        let by_frequency = grid.antinodes_by_frequency(&Resonance::part2());
+        let svg = grid.render_svg(&by_frequency);
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<text").count(), 7);
+        let path = env::temp_dir().join("day8_test_map.png");
+        grid.write_png(&by_frequency, path.to_str().unwrap())
+            .unwrap();
+        let png = fs::read(&path).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+        // IHDR holds the width and height right after the signature and chunk header.
+        assert_eq!(&png[16..24], &[0, 0, 0, 72, 0, 0, 0, 72]);
+        fs::remove_file(path).unwrap();
+        assert_eq!(escape_xml('<'), "&lt;");
+        assert_eq!(Colour::spread(0, 3), Colour(255, 0, 0));
     }
 }
@@ -1,133 +1,650 @@
-use std::cell::Cell;
+use itertools::Itertools;
 use std::cmp::Reverse;
-use std::collections::BinaryHeap;
-use std::{collections::HashMap, fs};
+use std::collections::{BTreeSet, VecDeque};
+use std::{env, fmt, fs, process, str::FromStr};
 
-#[derive(Debug, Clone, Copy)]
+// A run of blocks in disk order: free space, or all or part of the file with the given id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Block {
     Free(usize),
-    File(usize, usize, bool),
+    File(usize, usize),
+}
+
+impl Block {
+    fn len(&self) -> usize {
+        match *self {
+            Block::Free(n) | Block::File(_, n) => n,
+        }
+    }
 }
 
 fn load(path: &str) -> String {
     fs::read_to_string(path).expect("File not found")
 }
 
-fn part1(map: &str) -> usize {
-    let mut free: Vec<usize> = Vec::new();
-    let mut blocks: Vec<Block> = Vec::new();
-    let mut num_blocks = 0;
-    for (j, c) in map.chars().enumerate() {
-        let n = c.to_digit(10).unwrap() as usize;
-        let id = j / 2;
-        if j % 2 == 0 {
-            for _ in 0..n {
-                blocks.push(Block::File(id, 1, false));
-                num_blocks += 1;
+fn parse(map: &str) -> Vec<Block> {
+    map.trim_end()
+        .chars()
+        .enumerate()
+        .map(|(j, c)| {
+            let n = c.to_digit(10).expect("Expected a digit") as usize;
+            if j % 2 == 0 {
+                Block::File(j / 2, n)
+            } else {
+                Block::Free(n)
             }
-        } else {
-            for _ in 0..n {
-                free.push(blocks.len());
-                blocks.push(Block::Free(1));
+        })
+        .collect()
+}
+
+// The run lengths that `parse` or `parse_delimited` would read back as this layout, alternating
+// file and free space. Only layouts whose files appear whole and in id order have one; missing
+// ids become empty files.
+fn lengths(layout: &[Block]) -> Result<Vec<usize>, String> {
+    let mut lengths: Vec<usize> = Vec::new();
+    for block in normalise(layout) {
+        match block {
+            Block::File(id, n) => {
+                while lengths.len() % 2 == 1 || lengths.len() / 2 < id {
+                    lengths.push(0);
+                }
+                if lengths.len() / 2 > id {
+                    return Err(format!("File {id} is out of order or split"));
+                }
+                lengths.push(n);
             }
-        }
-    }
-    free.reverse();
-    while blocks.len() > num_blocks {
-        match blocks.pop() {
-            Some(Block::Free(_)) => continue,
-            Some(Block::File(id, _, _)) => {
-                let idx = free.pop().unwrap();
-                blocks[idx] = Block::File(id, 1, false);
+            Block::Free(n) => {
+                if lengths.len().is_multiple_of(2) {
+                    lengths.push(0);
+                }
+                lengths.push(n);
             }
-            None => unreachable!(),
         }
     }
-    blocks
+    Ok(lengths)
+}
+
+// The inverse of `parse`.
+fn encode(layout: &[Block]) -> Result<String, String> {
+    lengths(layout)?
         .iter()
+        .map(|&n| {
+            char::from_digit(n as u32, 10)
+                .filter(|_| n < 10)
+                .ok_or_else(|| format!("Length {n} needs more than one digit"))
+        })
+        .collect()
+}
+
+// The inverse of `parse_delimited`.
+fn encode_delimited(layout: &[Block]) -> Result<String, String> {
+    Ok(lengths(layout)?.iter().join(","))
+}
+
+// The ways a disk can be written down. They overlap, e.g. `12` is a dense map, one delimited
+// length or a block string, so the format is always given rather than guessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    // One digit per length, as in the puzzle input.
+    Dense,
+    // Lengths of any size, as read by `parse_delimited`.
+    Delimited,
+    // One character per block, as read by `collapse`.
+    Blocks,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dense" => Ok(Format::Dense),
+            "delimited" => Ok(Format::Delimited),
+            "blocks" => Ok(Format::Blocks),
+            _ => Err(format!("Unknown format {s}")),
+        }
+    }
+}
+
+fn read_layout(raw: &str, format: Format) -> Result<Vec<Block>, String> {
+    let raw = raw.trim();
+    match format {
+        Format::Dense => match raw.chars().find(|c| !c.is_ascii_digit()) {
+            Some(c) => Err(format!("Invalid length {c}")),
+            None => Ok(parse(raw)),
+        },
+        Format::Delimited => parse_delimited(raw),
+        Format::Blocks => collapse(raw),
+    }
+}
+
+// The inverse of `read_layout`. Fails rather than switch formats when this one can't hold the
+// layout.
+fn write_layout(layout: &[Block], format: Format) -> Result<String, String> {
+    match format {
+        Format::Dense => encode(layout),
+        Format::Delimited => encode_delimited(layout),
+        Format::Blocks => expand(layout),
+    }
+}
+
+// Merges neighbouring runs of free space or of the same file, and drops empty runs.
+fn normalise(layout: &[Block]) -> Vec<Block> {
+    let mut merged: Vec<Block> = Vec::new();
+    for &block in layout.iter().filter(|b| b.len() > 0) {
+        match (merged.last_mut(), block) {
+            (Some(Block::Free(n)), Block::Free(m)) => *n += m,
+            (Some(Block::File(id, n)), Block::File(other, m)) if *id == other => *n += m,
+            _ => merged.push(block),
+        }
+    }
+    merged
+}
+
+// File ids in the block string, one character each.
+const SYMBOLS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+// One character per block, as in `00...111...2`.
+fn expand(layout: &[Block]) -> Result<String, String> {
+    let mut blocks = String::new();
+    for block in layout {
+        let symbol = match *block {
+            Block::Free(_) => '.',
+            Block::File(id, _) => SYMBOLS
+                .get(id)
+                .map(|&s| char::from(s))
+                .ok_or_else(|| format!("File {id} has no single-character symbol"))?,
+        };
+        blocks.extend(std::iter::repeat_n(symbol, block.len()));
+    }
+    Ok(blocks)
+}
+
+fn collapse(blocks: &str) -> Result<Vec<Block>, String> {
+    let mut layout = Vec::new();
+    for c in blocks.trim_end().chars() {
+        let block = match c {
+            '.' => Block::Free(1),
+            _ => Block::File(
+                SYMBOLS
+                    .iter()
+                    .position(|&s| char::from(s) == c)
+                    .ok_or_else(|| format!("Unknown block {c}"))?,
+                1,
+            ),
+        };
+        layout.push(block);
+    }
+    Ok(normalise(&layout))
+}
+
+// The layout before and after compaction, one character per block. Ids past the last symbol
+// wrap around, so large disks still render, if ambiguously.
+fn render(before: &[Block], after: &[Block]) -> String {
+    let line = |layout: &[Block]| {
+        let wrapped: Vec<Block> = layout
+            .iter()
+            .map(|&b| match b {
+                Block::File(id, n) => Block::File(id % SYMBOLS.len(), n),
+                free => free,
+            })
+            .collect();
+        expand(&wrapped).expect("Every wrapped id has a symbol")
+    };
+    format!("before: {}\nafter:  {}", line(before), line(after))
+}
+
+fn is_delimiter(c: char) -> bool {
+    c == ',' || c.is_whitespace()
+}
+
+// Like the dense map, but lengths of any size, separated by commas or whitespace:
+// `12,3,100,0,5`.
+fn parse_delimited(description: &str) -> Result<Vec<Block>, String> {
+    description
+        .split(is_delimiter)
+        .filter(|s| !s.is_empty())
         .enumerate()
-        .map(|(j, b)| {
-            j * match b {
-                Block::File(id, _, _) => *id,
-                Block::Free(_) => 0,
-            }
+        .map(|(j, s)| {
+            let n = s.parse().map_err(|_| format!("Invalid length {s}"))?;
+            Ok(if j % 2 == 0 {
+                Block::File(j / 2, n)
+            } else {
+                Block::Free(n)
+            })
         })
-        .sum()
+        .collect()
 }
 
-fn part2(map: &str) -> usize {
-    let mut free: Vec<BinaryHeap<Reverse<Cell<usize>>>> = vec![BinaryHeap::new(); 10];
-    let mut blocks: Vec<Block> = Vec::new();
-    for (j, c) in map.chars().enumerate() {
-        let n = c.to_digit(10).unwrap() as usize;
-        let id = j / 2;
-        if j % 2 == 0 {
-            blocks.push(Block::File(id, n, false));
-        } else {
-            free[n].push(Reverse(Cell::new(j)));
-            blocks.push(Block::Free(n));
+// The free spans of a disk in disk order, under a max segment tree of their lengths, so the
+// leftmost span with room for a file is found in O(log n).
+struct FreeSpans {
+    spans: Vec<(usize, usize)>,
+    tree: Vec<usize>,
+}
+
+impl FreeSpans {
+    fn new(spans: Vec<(usize, usize)>) -> Self {
+        let size = spans.len().next_power_of_two();
+        let mut tree = vec![0; 2 * size];
+        for (k, &(_, len)) in spans.iter().enumerate() {
+            tree[size + k] = len;
+        }
+        for k in (1..size).rev() {
+            tree[k] = tree[2 * k].max(tree[2 * k + 1]);
+        }
+        Self { spans, tree }
+    }
+
+    // The leftmost span of at least `len` blocks, if it starts before `before`.
+    fn leftmost(&self, len: usize, before: usize) -> Option<usize> {
+        if self.tree[1] < len {
+            return None;
+        }
+        let size = self.tree.len() / 2;
+        let mut node = 1;
+        while node < size {
+            node = if self.tree[2 * node] >= len {
+                2 * node
+            } else {
+                2 * node + 1
+            };
         }
+        let k = node - size;
+        (self.spans[k].0 < before).then_some(k)
     }
-    let mut reordered_blocks = blocks.clone();
-    let mut block_placement: HashMap<usize, usize> = HashMap::new();
-    for (j, b) in blocks.iter().enumerate() {
-        if let Block::File(id, _, _) = b {
-            block_placement.insert(*id, j);
+
+    // Fills the first `len` blocks of span `k` and returns where they start.
+    fn take(&mut self, k: usize, len: usize) -> usize {
+        let (start, available) = self.spans[k];
+        self.spans[k] = (start + len, available - len);
+        let mut node = self.tree.len() / 2 + k;
+        self.tree[node] = available - len;
+        while node > 1 {
+            node /= 2;
+            self.tree[node] = self.tree[2 * node].max(self.tree[2 * node + 1]);
         }
+        start
     }
-    for b in blocks.iter().rev() {
-        if let Block::File(id, n, false) = b {
-            let current_spot = block_placement.get(id).unwrap();
-            let available_len: Vec<usize> = (*n..10).filter(|&k| !free[k].is_empty()).collect();
-            if let Some((k, new_spot)) = available_len
+}
+
+// Where whole files go among the free spans in front of them.
+trait FreeSpace {
+    // Fills `len` blocks of a span that starts before `before` and returns where they start.
+    // Files are placed right to left, so `before` never grows from one call to the next.
+    fn place(&mut self, len: usize, before: usize) -> Option<usize>;
+}
+
+impl FreeSpace for FreeSpans {
+    fn place(&mut self, len: usize, before: usize) -> Option<usize> {
+        let k = self.leftmost(len, before)?;
+        Some(self.take(k, len))
+    }
+}
+
+// Free spans ordered by length, for placing files in the smallest or the largest span that
+// fits, the leftmost one on a tie.
+struct SizedSpans {
+    spans: Vec<(usize, usize)>,
+    by_len: BTreeSet<(usize, usize)>,
+    // Spans from here on start too late to take any file still to be placed.
+    usable: usize,
+    largest: bool,
+}
+
+impl SizedSpans {
+    fn new(spans: Vec<(usize, usize)>, largest: bool) -> Self {
+        Self {
+            by_len: spans
                 .iter()
-                .map(|&k| (k, free[k].peek().unwrap().0.get()))
-                .filter(|(_, v)| *v < *current_spot)
-                .min_by_key(|&(_, v)| v)
-            {
-                reordered_blocks[new_spot] = Block::File(*id, *n, true);
-                reordered_blocks[*current_spot] = Block::Free(*n);
-                free[*n].push(Reverse(Cell::new(*current_spot)));
-                if k > *n {
-                    reordered_blocks.insert(new_spot + 1, Block::Free(k - *n));
-                    for slots in free.iter() {
-                        for sp in slots.iter() {
-                            if sp.0.get() >= new_spot {
-                                sp.0.set(sp.0.get() + 1);
-                            }
-                        }
-                    }
-                    for k in block_placement.values_mut() {
-                        if *k > new_spot {
-                            *k += 1;
-                        }
+                .enumerate()
+                .map(|(k, &(_, len))| (len, k))
+                .collect(),
+            usable: spans.len(),
+            spans,
+            largest,
+        }
+    }
+}
+
+impl FreeSpace for SizedSpans {
+    fn place(&mut self, len: usize, before: usize) -> Option<usize> {
+        while self.usable > 0 && self.spans[self.usable - 1].0 >= before {
+            self.usable -= 1;
+            self.by_len
+                .remove(&(self.spans[self.usable].1, self.usable));
+        }
+        let &(available, k) = if self.largest {
+            let &(most, _) = self.by_len.last()?;
+            self.by_len.range((most, 0)..).next()?
+        } else {
+            self.by_len.range((len, 0)..).next()?
+        };
+        if available < len {
+            return None;
+        }
+        self.by_len.remove(&(available, k));
+        self.by_len.insert((available - len, k));
+        let start = self.spans[k].0;
+        self.spans[k] = (start + len, available - len);
+        Some(start)
+    }
+}
+
+// The final layout of a compaction, its checksum and how many moves it took: blocks for block
+// compaction, files otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Compaction {
+    layout: Vec<Block>,
+    checksum: u128,
+    moves: usize,
+}
+
+impl Compaction {
+    fn new(layout: Vec<Block>, moves: usize) -> Self {
+        Self {
+            checksum: checksum(&layout),
+            layout,
+            moves,
+        }
+    }
+}
+
+trait CompactionStrategy: fmt::Display {
+    fn compact(&self, layout: &[Block]) -> Compaction;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Strategy {
+    // Single blocks from the end of the disk into the leftmost free block.
+    Blocks,
+    // Whole files into the leftmost span that fits.
+    FirstFit,
+    // Whole files into the smallest span that fits.
+    BestFit,
+    // Whole files into the largest span.
+    WorstFit,
+    // First fit, then the files close up around whichever of them can stay put, the ones before
+    // it moving right and the ones after it left, until no gaps are left.
+    Defrag,
+}
+
+const STRATEGIES: [Strategy; 5] = [
+    Strategy::Blocks,
+    Strategy::FirstFit,
+    Strategy::BestFit,
+    Strategy::WorstFit,
+    Strategy::Defrag,
+];
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Strategy::Blocks => write!(f, "blocks"),
+            Strategy::FirstFit => write!(f, "first-fit"),
+            Strategy::BestFit => write!(f, "best-fit"),
+            Strategy::WorstFit => write!(f, "worst-fit"),
+            Strategy::Defrag => write!(f, "defrag"),
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        STRATEGIES
+            .into_iter()
+            .find(|strategy| strategy.to_string() == s)
+            .ok_or_else(|| format!("Unknown strategy {s}"))
+    }
+}
+
+impl CompactionStrategy for Strategy {
+    fn compact(&self, layout: &[Block]) -> Compaction {
+        let (files, spans) = extents(layout);
+        let total = layout.iter().map(Block::len).sum();
+        let (layout, moves) = match self {
+            Strategy::Blocks => compact_blocks(layout),
+            Strategy::FirstFit => move_files(files, &mut FreeSpans::new(spans), total),
+            Strategy::BestFit => move_files(files, &mut SizedSpans::new(spans, false), total),
+            Strategy::WorstFit => move_files(files, &mut SizedSpans::new(spans, true), total),
+            Strategy::Defrag => {
+                let (packed, moves) = move_files(files, &mut FreeSpans::new(spans), total);
+                let (files, _) = extents(&packed);
+                let (files, slides) = close_gaps(files);
+                (assemble(files, total), moves + slides)
+            }
+        };
+        Compaction::new(layout, moves)
+    }
+}
+
+// Moves single blocks from the end of the disk into the leftmost free block until no gaps remain.
+fn compact_blocks(layout: &[Block]) -> (Vec<Block>, usize) {
+    let mut files: VecDeque<(usize, usize)> = layout
+        .iter()
+        .filter_map(|b| match *b {
+            Block::File(id, n) if n > 0 => Some((id, n)),
+            _ => None,
+        })
+        .collect();
+    let total: usize = layout.iter().map(Block::len).sum();
+    let mut compacted = Vec::new();
+    let mut moves = 0;
+    for block in layout {
+        match *block {
+            Block::File(_, 0) => {}
+            Block::File(id, _) => match files.front() {
+                Some(&(front, n)) if front == id => {
+                    compacted.push(Block::File(id, n));
+                    files.pop_front();
+                }
+                _ => break,
+            },
+            Block::Free(mut n) => {
+                while n > 0 {
+                    let Some((id, len)) = files.back_mut() else {
+                        break;
+                    };
+                    let moved = n.min(*len);
+                    compacted.push(Block::File(*id, moved));
+                    moves += moved;
+                    *len -= moved;
+                    n -= moved;
+                    if *len == 0 {
+                        files.pop_back();
                     }
-                    free[k - *n].push(Reverse(Cell::new(new_spot + 1)));
                 }
-                free[k].pop();
             }
         }
+        if files.is_empty() {
+            break;
+        }
     }
-    let mut idx = 0;
-    let mut res = 0;
-    for b in reordered_blocks {
-        match b {
-            Block::Free(n) => {
-                idx += n;
-            }
-            Block::File(id, n, _) => {
-                res += id * (n * idx + n * (n - 1) / 2);
-                idx += n;
+    let used: usize = compacted.iter().map(Block::len).sum();
+    if total > used {
+        compacted.push(Block::Free(total - used));
+    }
+    (compacted, moves)
+}
+
+// A file as its start, id and length.
+type FileExtent = (usize, usize, usize);
+
+// The files of a layout, and its free spans as (start, len).
+fn extents(layout: &[Block]) -> (Vec<FileExtent>, Vec<(usize, usize)>) {
+    let mut files = Vec::new();
+    let mut spans = Vec::new();
+    let mut position = 0;
+    for block in layout {
+        match *block {
+            Block::File(id, n) => files.push((position, id, n)),
+            // A zero-length file leaves the free space either side of it as one span.
+            Block::Free(n) => match spans.last_mut() {
+                Some((start, len)) if *start + *len == position => *len += n,
+                _ => spans.push((position, n)),
+            },
+        }
+        position += block.len();
+    }
+    (files, spans)
+}
+
+// Lays files out at their starts, with free space in between and up to `total` blocks.
+fn assemble(mut files: Vec<FileExtent>, total: usize) -> Vec<Block> {
+    files.sort_unstable();
+    let mut layout = Vec::new();
+    let mut end = 0;
+    for (start, id, n) in files {
+        if n == 0 {
+            continue;
+        }
+        if start > end {
+            layout.push(Block::Free(start - end));
+        }
+        layout.push(Block::File(id, n));
+        end = start + n;
+    }
+    if total > end {
+        layout.push(Block::Free(total - end));
+    }
+    layout
+}
+
+// Moves each file once, highest id first, into a free span before it that can hold all of it.
+// Files only ever move left, past files with lower ids, so the space a file leaves behind is
+// never of use to the files still to move.
+fn move_files(
+    mut files: Vec<FileExtent>,
+    free: &mut dyn FreeSpace,
+    total: usize,
+) -> (Vec<Block>, usize) {
+    let mut moves = 0;
+    for (start, _, n) in files.iter_mut().rev() {
+        if *n > 0 {
+            if let Some(target) = free.place(*n, *start) {
+                *start = target;
+                moves += 1;
             }
         }
     }
+    (assemble(files, total), moves)
+}
+
+// Packs the files together in disk order at the offset that leaves the most of them in place, so
+// the fewest move. Files before their packed position move right, the rest move left, and ties go
+// to the lowest offset to keep free space at the end of the disk.
+fn close_gaps(mut files: Vec<FileExtent>) -> (Vec<FileExtent>, usize) {
+    files.retain(|&(_, _, n)| n > 0);
+    let offsets: Vec<usize> = files
+        .iter()
+        .scan(0, |used, &(start, _, n)| {
+            let offset = start - *used;
+            *used += n;
+            Some(offset)
+        })
+        .collect();
+    let offset = offsets
+        .iter()
+        .counts()
+        .into_iter()
+        .max_by_key(|&(&offset, count)| (count, Reverse(offset)))
+        .map_or(0, |(&offset, _)| offset);
+    let mut slides = 0;
+    for ((start, _, _), o) in files.iter_mut().zip(offsets) {
+        if o != offset {
+            *start = *start - o + offset;
+            slides += 1;
+        }
+    }
+    (files, slides)
+}
+
+// In 128 bits, since positions times ids outgrow 64 bits on large disks.
+fn checksum(layout: &[Block]) -> u128 {
+    let overflow = || panic!("Checksum exceeds 128 bits");
+    let mut idx: u128 = 0;
+    let mut res: u128 = 0;
+    for b in layout {
+        let n = b.len() as u128;
+        if let Block::File(id, _) = *b {
+            // The positions idx..idx + n add up to n * idx + n * (n - 1) / 2.
+            let positions = n
+                .checked_mul(idx)
+                .and_then(|p| p.checked_add(n * n.saturating_sub(1) / 2))
+                .unwrap_or_else(overflow);
+            res = (id as u128)
+                .checked_mul(positions)
+                .and_then(|c| res.checked_add(c))
+                .unwrap_or_else(overflow);
+        }
+        idx += n;
+    }
     res
 }
 
+fn part1(layout: &[Block]) -> u128 {
+    Strategy::Blocks.compact(layout).checksum
+}
+
+fn part2(layout: &[Block]) -> u128 {
+    Strategy::FirstFit.compact(layout).checksum
+}
+
 fn main() {
-    let map = load("input.txt");
-    println!("Part 1: {}", part1(&map));
-    println!("Part 2: {}", part2(&map));
+    let args: Vec<String> = env::args().collect();
+    let format: Format = match args.iter().position(|a| a == "--format") {
+        Some(k) => args[k + 1].parse().expect("Invalid format"),
+        None => Format::Dense,
+    };
+    let path = match args.iter().position(|a| a == "--disk") {
+        Some(k) => args[k + 1].as_str(),
+        None => "input.txt",
+    };
+    let layout = match read_layout(&load(path), format) {
+        Ok(layout) => layout,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            process::exit(1);
+        }
+    };
+    println!("Part 1: {}", part1(&layout));
+    println!("Part 2: {}", part2(&layout));
+    if args.iter().any(|a| a == "--compare") {
+        for strategy in STRATEGIES {
+            let compaction = strategy.compact(&layout);
+            println!(
+                "{:>10}  checksum {:>15}  moves {:>6}",
+                strategy.to_string(),
+                compaction.checksum,
+                compaction.moves
+            );
+        }
+    }
+    if let Some(k) = args.iter().position(|a| a == "--show") {
+        let strategy: Strategy = args[k + 1].parse().expect("Invalid strategy");
+        let layout = match args.get(k + 2) {
+            Some(path) => read_layout(&load(path), format).expect("Invalid disk"),
+            None => layout.clone(),
+        };
+        println!("{}", render(&layout, &strategy.compact(&layout).layout));
+    }
+    if let Some(k) = args.iter().position(|a| a == "--encode") {
+        let layout = read_layout(&load(&args[k + 1]), format).expect("Invalid disk");
+        let to: Format = args
+            .get(k + 2)
+            .expect("Expected a format to encode to")
+            .parse()
+            .expect("Invalid format");
+        match write_layout(&layout, to) {
+            Ok(encoded) => println!("{encoded}"),
+            Err(e) => {
+                eprintln!("{e}");
+                process::exit(1);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -137,12 +654,264 @@ mod test {
     #[test]
     fn test_part1() {
         let map = load("test.txt");
-        assert_eq!(part1(&map), 1928)
+        assert_eq!(part1(&parse(&map)), 1928)
     }
 
     #[test]
     fn test_part2() {
         let map = load("test.txt");
-        assert_eq!(part2(&map), 2858)
+        assert_eq!(part2(&parse(&map)), 2858)
+    }
+
+    fn generate(len: usize, seed: u64) -> String {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                char::from(b'0' + (state >> 33) as u8 % 10)
+            })
+            .collect()
+    }
+
+    // Both compactions done one block at a time on the expanded disk.
+    fn reference(map: &str, whole_files: bool) -> u128 {
+        let mut disk: Vec<Option<usize>> = Vec::new();
+        for (j, c) in map.chars().enumerate() {
+            let id = (j % 2 == 0).then_some(j / 2);
+            disk.extend(std::iter::repeat_n(id, c.to_digit(10).unwrap() as usize));
+        }
+        if whole_files {
+            for id in (0..map.len().div_ceil(2)).rev() {
+                let Some(start) = disk.iter().position(|&b| b == Some(id)) else {
+                    continue;
+                };
+                let n = disk[start..].iter().take_while(|&&b| b == Some(id)).count();
+                if let Some(target) =
+                    (0..start).find(|&k| disk[k..k + n].iter().all(|b| b.is_none()))
+                {
+                    disk[target..target + n].fill(Some(id));
+                    disk[start..start + n].fill(None);
+                }
+            }
+        } else {
+            let (mut left, mut right) = (0, disk.len());
+            loop {
+                while left < disk.len() && disk[left].is_some() {
+                    left += 1;
+                }
+                while right > 0 && disk[right - 1].is_none() {
+                    right -= 1;
+                }
+                if left + 1 > right {
+                    break;
+                }
+                disk.swap(left, right - 1);
+            }
+        }
+        disk.iter()
+            .enumerate()
+            .map(|(k, b)| (k * b.unwrap_or(0)) as u128)
+            .sum()
+    }
+
+    #[test]
+    fn test_generated_maps() {
+        for seed in 0..10 {
+            let map = generate(101, seed);
+            assert_eq!(part1(&parse(&map)), reference(&map, false), "{map}");
+            assert_eq!(part2(&parse(&map)), reference(&map, true), "{map}");
+        }
+        // Ids up to 199, so files past single digits are compacted too.
+        let map = load("test_long.txt");
+        assert_eq!(part1(&parse(&map)), reference(&map, false));
+        assert_eq!(part2(&parse(&map)), reference(&map, true));
+    }
+
+    #[test]
+    fn test_free_spans() {
+        let mut free = FreeSpans::new(vec![(2, 1), (5, 3), (10, 2), (14, 4)]);
+        assert_eq!(free.leftmost(2, 20), Some(1));
+        assert_eq!(free.leftmost(4, 20), Some(3));
+        assert_eq!(free.leftmost(4, 14), None);
+        assert_eq!(free.leftmost(5, 20), None);
+        assert_eq!(free.take(1, 2), 5);
+        assert_eq!(free.leftmost(2, 20), Some(2));
+        assert_eq!(free.leftmost(1, 20), Some(0));
+        assert_eq!(free.take(0, 1), 2);
+        assert_eq!(free.leftmost(1, 20), Some(1));
+        assert_eq!(free.spans[1], (7, 1));
+    }
+
+    #[test]
+    fn test_strategies() {
+        let layout = parse(&load("test.txt"));
+        let first_fit = Strategy::FirstFit.compact(&layout);
+        // 99, 777, 44 and 2 move; nothing else fits anywhere earlier.
+        assert_eq!(first_fit.moves, 4);
+        assert_eq!(first_fit.checksum, 2858);
+        let blocks = Strategy::Blocks.compact(&layout);
+        assert_eq!(blocks.checksum, 1928);
+        assert_eq!(blocks.moves, 12);
+        for strategy in STRATEGIES {
+            let compaction = strategy.compact(&layout);
+            assert_eq!(strategy.to_string().parse(), Ok(strategy));
+            assert_eq!(compaction.checksum, checksum(&compaction.layout));
+            assert_eq!(
+                compaction.layout.iter().map(Block::len).sum::<usize>(),
+                layout.iter().map(Block::len).sum::<usize>()
+            );
+        }
+        let defrag = Strategy::Defrag.compact(&layout);
+        let (last, rest) = defrag.layout.split_last().unwrap();
+        assert!(matches!(last, Block::Free(_)));
+        assert!(rest.iter().all(|b| matches!(b, Block::File(..))));
+        // The gap is too small for any file to move into, so closing it moves the one file before
+        // it right rather than the three after it left.
+        let defrag = Strategy::Defrag.compact(&collapse("0..111222333").unwrap());
+        assert_eq!(expand(&defrag.layout).unwrap(), "..0111222333");
+        assert_eq!(defrag.moves, 1);
+        let defrag = Strategy::Defrag.compact(&collapse("00.111..2222").unwrap());
+        assert_eq!(expand(&defrag.layout).unwrap(), "001112222...");
+        assert_eq!(defrag.moves, 2);
+        // Files 1, 2 and 3 are single blocks after spans of 5 and 2. Best fit puts 3 in the
+        // span of 2, then 2 in what is left of it, and only 1 in the span of 5.
+        let layout = parse("1512101");
+        let starts = |strategy: Strategy| {
+            let (files, _) = extents(&strategy.compact(&layout).layout);
+            files
+                .iter()
+                .map(|&(start, id, _)| (id, start))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            starts(Strategy::FirstFit),
+            vec![(0, 0), (3, 1), (2, 2), (1, 3)]
+        );
+        assert_eq!(
+            starts(Strategy::BestFit),
+            vec![(0, 0), (1, 1), (3, 7), (2, 8)]
+        );
+        assert_eq!(
+            starts(Strategy::WorstFit),
+            vec![(0, 0), (3, 1), (2, 2), (1, 3)]
+        );
+        assert!("fastest".parse::<Strategy>().is_err());
+    }
+
+    #[test]
+    fn test_codec() {
+        let map = load("test.txt");
+        let layout = parse(&map);
+        assert_eq!(
+            expand(&layout).unwrap(),
+            "00...111...2...333.44.5555.6666.777.888899"
+        );
+        assert_eq!(encode(&layout).unwrap(), map.trim_end());
+        assert_eq!(
+            collapse("00...111...2...333.44.5555.6666.777.888899").unwrap(),
+            normalise(&layout)
+        );
+        let blocks = Strategy::Blocks.compact(&layout).layout;
+        assert_eq!(
+            expand(&blocks).unwrap(),
+            "0099811188827773336446555566.............."
+        );
+        let files = Strategy::FirstFit.compact(&layout).layout;
+        assert_eq!(
+            expand(&files).unwrap(),
+            "00992111777.44.333....5555.6666.....8888.."
+        );
+        assert!(encode(&files).is_err());
+        assert_eq!(
+            render(&layout, &files),
+            "before: 00...111...2...333.44.5555.6666.777.888899\n\
+             after:  00992111777.44.333....5555.6666.....8888.."
+        );
+        // Fixtures can be written block by block; ids that never appear are empty files.
+        let fixture = collapse("0..2a..").unwrap();
+        assert_eq!(encode(&fixture).unwrap(), "1200100000000000000012");
+        assert_eq!(collapse(&expand(&fixture).unwrap()).unwrap(), fixture);
+        assert_eq!(parse("1200100000000000000012").len(), 22);
+        assert_eq!(read_layout("0..2a..\n", Format::Blocks), Ok(fixture));
+        assert_eq!(read_layout(&map, Format::Dense), Ok(layout.clone()));
+        // Without free blocks, a block string is all digits too.
+        assert_eq!(
+            read_layout("0011", Format::Blocks),
+            Ok(vec![Block::File(0, 2), Block::File(1, 2)])
+        );
+        assert_eq!(
+            read_layout("0011", Format::Dense),
+            Ok(vec![
+                Block::File(0, 0),
+                Block::Free(0),
+                Block::File(1, 1),
+                Block::Free(1)
+            ])
+        );
+        assert!(read_layout("0..2", Format::Dense).is_err());
+        assert!("sparse".parse::<Format>().is_err());
+        for format in [Format::Dense, Format::Delimited, Format::Blocks] {
+            let written = write_layout(&layout, format).unwrap();
+            let read = read_layout(&written, format).unwrap();
+            assert_eq!(normalise(&read), normalise(&layout));
+        }
+        // A dense map can't hold a length of 12, and reading "12" back as one would be wrong.
+        let long = [Block::File(0, 12)];
+        assert!(write_layout(&long, Format::Dense).is_err());
+        assert_eq!(write_layout(&long, Format::Delimited).unwrap(), "12");
+        assert!(collapse("00?").is_err());
+        assert!(encode(&[Block::File(0, 12)]).is_err());
+        assert!(expand(&[Block::File(62, 1)]).is_err());
+    }
+
+    #[test]
+    fn test_large_disks() {
+        let layout = parse_delimited("12, 3,100,0 5\n1000000").unwrap();
+        assert_eq!(
+            layout,
+            vec![
+                Block::File(0, 12),
+                Block::Free(3),
+                Block::File(1, 100),
+                Block::Free(0),
+                Block::File(2, 5),
+                Block::Free(1000000)
+            ]
+        );
+        assert_eq!(
+            read_layout("12,3,100,0,5,1000000", Format::Delimited),
+            Ok(layout.clone())
+        );
+        // A single length has no delimiter to tell it from a dense map.
+        assert_eq!(
+            read_layout("12", Format::Delimited),
+            Ok(vec![Block::File(0, 12)])
+        );
+        assert_eq!(
+            read_layout("12", Format::Dense),
+            Ok(vec![Block::File(0, 1), Block::Free(2)])
+        );
+        assert_eq!(encode_delimited(&layout).unwrap(), "12,3,100,0,5,1000000");
+        assert!(encode(&layout).is_err());
+        assert!(parse_delimited("1,x").is_err());
+        let digits = load("test.txt");
+        let delimited = digits.trim().chars().join(",");
+        assert_eq!(parse_delimited(&delimited).unwrap(), parse(&digits));
+        let layout = read_layout(&delimited, Format::Delimited).unwrap();
+        assert_eq!((part1(&layout), part2(&layout)), (1928, 2858));
+        // 2^20 blocks of file 2^40 after 2^40 free blocks: well past 64 bits.
+        let (id, n, idx) = (1u128 << 40, 1u128 << 20, 1u128 << 40);
+        let huge = [
+            Block::File(0, 1),
+            Block::Free(1 << 40),
+            Block::File(1 << 40, 1 << 20),
+        ];
+        assert_eq!(checksum(&huge), id * (n * (idx + 1) + n * (n - 1) / 2));
+        let compacted = Strategy::FirstFit.compact(&huge);
+        assert_eq!(compacted.moves, 1);
+        assert_eq!(compacted.checksum, id * (n + n * (n - 1) / 2));
+        assert_eq!(Strategy::Blocks.compact(&huge).checksum, compacted.checksum);
     }
 }
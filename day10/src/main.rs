@@ -1,134 +1,444 @@
-use std::{
-    collections::{HashMap, HashSet},
-    fs,
-};
+use itertools::Itertools;
+use std::{env, fmt, fs, process, str::FromStr};
 
-type GridPoint = (i8, i8);
+type GridPoint = (usize, usize);
 
-struct Node {
-    value: i8,
-    neighbours: Vec<GridPoint>,
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MapError {
+    InvalidCell { x: usize, y: usize, c: char },
+    RaggedRow { y: usize, len: usize, width: usize },
 }
 
-struct Graph {
-    nodes: HashMap<GridPoint, Node>,
-    trailheads: Vec<GridPoint>,
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::InvalidCell { x, y, c } => write!(f, "Invalid cell {c:?} at ({x},{y})"),
+            MapError::RaggedRow { y, len, width } => {
+                write!(f, "Row {y} has {len} cells, expected {width}")
+            }
+        }
+    }
 }
 
-impl Graph {
-    fn new(path: &str) -> Self {
-        let mut width: i8 = 0;
-        let mut height: i8 = 0;
-        let mut nodes = HashMap::new();
-        let mut trailheads = Vec::new();
-        fs::read_to_string(path)
-            .expect("File not found")
-            .split('\n')
-            .enumerate()
-            .for_each(|(y, s)| {
-                width = s.len() as i8;
-                height += 1;
-                s.chars().enumerate().for_each(|(x, c)| {
-                    let v = c.to_digit(10).expect("Expected a i8") as i8;
-                    let p = (x as i8, y as i8);
-                    nodes.insert(
-                        p,
-                        Node {
-                            value: v,
-                            neighbours: Vec::new(),
-                        },
-                    );
-                    if v == 0 {
-                        trailheads.push(p);
-                    }
-                })
-            });
-        for y in 0..height {
-            for x in 0..width {
-                let p = (x, y);
-                let u = nodes.get(&p).expect("Inconsistent input").value;
-                let mut neighbours = Vec::new();
-                for dy in -1i8..2i8 {
-                    for dx in -1i8..2i8 {
-                        if dx == 0 && dy == 0 || dx != 0 && dy != 0 {
-                            continue;
-                        }
-                        let q = (x + dx, y + dy);
-                        let v = nodes.get(&q).map(|n| n.value).unwrap_or(-2);
-                        if v - u == 1 {
-                            neighbours.push(q);
-                        }
+// How trails move over the map. Every step must change height by at least one in the trail's
+// direction, so a trail can never loop back on itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TrailRule {
+    // Largest height change allowed in a single step.
+    max_step: u8,
+    // Whether trails run downhill instead of up.
+    descending: bool,
+    // Whether trails may also move diagonally.
+    diagonal: bool,
+    trailhead: u8,
+    peak: u8,
+}
+
+impl Default for TrailRule {
+    fn default() -> Self {
+        Self {
+            max_step: 1,
+            descending: false,
+            diagonal: false,
+            trailhead: 0,
+            peak: 9,
+        }
+    }
+}
+
+impl TrailRule {
+    fn allows(&self, from: u8, to: u8) -> bool {
+        let change = if self.descending {
+            from as i16 - to as i16
+        } else {
+            to as i16 - from as i16
+        };
+        (1..=self.max_step as i16).contains(&change)
+    }
+}
+
+impl FromStr for TrailRule {
+    type Err = String;
+
+    // Comma-separated changes to the default, e.g. `step=2,descending,diagonal,trailhead=9,peak=0`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let height = |v: &str| match v.parse() {
+            Ok(h) if h <= 9 => Ok(h),
+            _ => Err(format!("Invalid height {v}")),
+        };
+        let mut rule = Self::default();
+        for item in s.split(',').filter(|i| !i.is_empty()) {
+            match item.split_once('=') {
+                None if item == "descending" => rule.descending = true,
+                None if item == "diagonal" => rule.diagonal = true,
+                Some(("step", v)) => {
+                    rule.max_step = match v.parse() {
+                        Ok(k) if k >= 1 => k,
+                        _ => return Err(format!("Invalid step {v}")),
                     }
                 }
-                nodes.get_mut(&p).expect("Inconsistent input").neighbours = neighbours;
+                Some(("trailhead", v)) => rule.trailhead = height(v)?,
+                Some(("peak", v)) => rule.peak = height(v)?,
+                _ => return Err(format!("Unknown trail rule {item}")),
             }
         }
-        Self { nodes, trailheads }
+        Ok(rule)
     }
-    fn collect_paths(&self, current_path: Vec<GridPoint>, paths: &mut Vec<Vec<GridPoint>>) {
-        let node = self.nodes.get(current_path.last().unwrap()).unwrap();
-        if node.value == 9 {
-            paths.push(current_path.clone());
-            return;
-        }
-        for n in node.neighbours.iter() {
-            let mut next_path = current_path.clone();
-            next_path.push(*n);
-            self.collect_paths(next_path, paths);
+}
+
+const ORTHOGONAL: [(isize, isize); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+const DIAGONAL: [(isize, isize); 4] = [(1, -1), (1, 1), (-1, 1), (-1, -1)];
+
+// Heights row by row, None where the map cannot be walked.
+struct TopoMap {
+    width: usize,
+    height: usize,
+    heights: Vec<Option<u8>>,
+}
+
+impl TopoMap {
+    // Every cell must be a digit, or a '.' for impassable ground if `impassable_dots` is set, as
+    // in some of the puzzle's examples.
+    fn parse(raw: &str, impassable_dots: bool) -> Result<Self, MapError> {
+        let mut width = 0;
+        let mut height = 0;
+        let mut heights = Vec::new();
+        for (y, row) in raw.trim_end().split('\n').enumerate() {
+            let mut len = 0;
+            for (x, c) in row.chars().enumerate() {
+                heights.push(match c.to_digit(10) {
+                    Some(v) => Some(v as u8),
+                    None if c == '.' && impassable_dots => None,
+                    None => return Err(MapError::InvalidCell { x, y, c }),
+                });
+                len += 1;
+            }
+            if y == 0 {
+                width = len;
+            } else if len != width {
+                return Err(MapError::RaggedRow { y, len, width });
+            }
+            height += 1;
         }
+        Ok(Self {
+            width,
+            height,
+            heights,
+        })
+    }
+
+    fn point(&self, k: usize) -> GridPoint {
+        (k % self.width, k / self.width)
+    }
+
+    fn index(&self, (x, y): GridPoint) -> usize {
+        y * self.width + x
+    }
+
+    fn neighbours(&self, k: usize, diagonal: bool) -> impl Iterator<Item = usize> + '_ {
+        let (x, y) = self.point(k);
+        let diagonals: &[(isize, isize)] = if diagonal { &DIAGONAL } else { &[] };
+        ORTHOGONAL
+            .iter()
+            .chain(diagonals)
+            .filter_map(move |&(dx, dy)| {
+                let x = x.checked_add_signed(dx).filter(|&x| x < self.width)?;
+                let y = y.checked_add_signed(dy).filter(|&y| y < self.height)?;
+                Some(self.index((x, y)))
+            })
     }
 }
 
-fn hiking_trails(graph: &Graph) -> Vec<Vec<Vec<GridPoint>>> {
-    let mut all_trails = Vec::new();
-    for p in graph.trailheads.iter() {
-        let mut paths = Vec::new();
-        let current_path = vec![*p];
-        graph.collect_paths(current_path, &mut paths);
-        all_trails.push(paths);
+struct Graph {
+    map: TopoMap,
+    rule: TrailRule,
+    // Cells a trail can step to next, by cell index.
+    neighbours: Vec<Vec<usize>>,
+    trailheads: Vec<usize>,
+}
+
+impl Graph {
+    fn load(path: &str, impassable_dots: bool, rule: TrailRule) -> Result<Self, MapError> {
+        let raw = fs::read_to_string(path).expect("File not found");
+        Ok(Self::from_map(TopoMap::parse(&raw, impassable_dots)?, rule))
+    }
+
+    fn from_map(map: TopoMap, rule: TrailRule) -> Self {
+        let neighbours = (0..map.heights.len())
+            .map(|k| match map.heights[k] {
+                Some(u) => map
+                    .neighbours(k, rule.diagonal)
+                    .filter(|&n| map.heights[n].is_some_and(|v| rule.allows(u, v)))
+                    .collect(),
+                None => Vec::new(),
+            })
+            .collect();
+        let trailheads = (0..map.heights.len())
+            .filter(|&k| map.heights[k] == Some(rule.trailhead))
+            .collect();
+        Self {
+            map,
+            rule,
+            neighbours,
+            trailheads,
+        }
+    }
+
+    // Every trail from `head`, one vector of points each. Trails can be exponentially many, so
+    // this is only for showing them; scoring uses `scores`.
+    fn trails_from(&self, head: GridPoint) -> Vec<Vec<GridPoint>> {
+        fn extend(graph: &Graph, trail: &mut Vec<usize>, trails: &mut Vec<Vec<GridPoint>>) {
+            let k = *trail.last().unwrap();
+            if graph.map.heights[k] == Some(graph.rule.peak) {
+                trails.push(trail.iter().map(|&k| graph.map.point(k)).collect());
+                return;
+            }
+            for &n in graph.neighbours[k].iter() {
+                trail.push(n);
+                extend(graph, trail, trails);
+                trail.pop();
+            }
+        }
+        let mut trails = Vec::new();
+        extend(self, &mut vec![self.map.index(head)], &mut trails);
+        trails
+    }
+
+    // Trail counts work back from the peaks, so each node's neighbours are done before it, and a
+    // node has the sum of theirs. Peaks are counted by a search from each trailhead instead, which
+    // stays local since every step changes height. Cells are stamped with the trailhead that last
+    // saw them, so memory stays linear in the map and nothing is cleared between searches.
+    fn scores(&self) -> Vec<Score> {
+        let heights = &self.map.heights;
+        let peak = Some(self.rule.peak);
+        let mut order: Vec<usize> = (0..heights.len())
+            .filter(|&k| heights[k].is_some())
+            .collect();
+        order.sort_unstable_by_key(|&k| {
+            let h = heights[k].unwrap() as i16;
+            if self.rule.descending {
+                h
+            } else {
+                -h
+            }
+        });
+        let mut trails = vec![0; heights.len()];
+        for k in order {
+            trails[k] = if heights[k] == peak {
+                1
+            } else {
+                self.neighbours[k].iter().map(|&n| trails[n]).sum()
+            };
+        }
+        let mut seen = vec![usize::MAX; heights.len()];
+        let mut stack = Vec::new();
+        self.trailheads
+            .iter()
+            .enumerate()
+            .map(|(stamp, &k)| {
+                let mut peaks = 0;
+                seen[k] = stamp;
+                stack.push(k);
+                while let Some(j) = stack.pop() {
+                    if heights[j] == peak {
+                        peaks += 1;
+                        continue;
+                    }
+                    for &n in self.neighbours[j].iter() {
+                        if seen[n] != stamp {
+                            seen[n] = stamp;
+                            stack.push(n);
+                        }
+                    }
+                }
+                Score {
+                    trailhead: self.map.point(k),
+                    peaks,
+                    trails: trails[k],
+                }
+            })
+            .collect()
     }
-    all_trails
 }
 
-fn part1(trails: &[Vec<Vec<GridPoint>>]) -> usize {
-    trails
-        .iter()
-        .map(|trails_for_head| {
-            trails_for_head
-                .iter()
-                .map(|trail| *trail.last().unwrap())
-                .collect::<HashSet<GridPoint>>()
-                .len()
-        })
-        .sum()
+struct Score {
+    trailhead: GridPoint,
+    // Distinct peaks reachable from the trailhead.
+    peaks: usize,
+    // Distinct trails from the trailhead to any peak.
+    trails: usize,
 }
 
-fn part2(trails: &[Vec<Vec<GridPoint>>]) -> usize {
-    trails.iter().map(|t| t.len()).sum()
+fn part1(graph: &Graph) -> usize {
+    graph.scores().iter().map(|s| s.peaks).sum()
+}
+
+fn part2(graph: &Graph) -> usize {
+    graph.scores().iter().map(|s| s.trails).sum()
 }
 
 fn main() {
-    let graph = Graph::new("input.txt");
-    let trails = hiking_trails(&graph);
-    println!("Part 1: {}", part1(&trails));
-    println!("Part 2: {}", part2(&trails));
+    let args: Vec<String> = env::args().collect();
+    let path = match args.iter().position(|a| a == "--map") {
+        Some(k) => args[k + 1].as_str(),
+        None => "input.txt",
+    };
+    let rule = match args.iter().position(|a| a == "--rule") {
+        Some(k) => args[k + 1].parse().expect("Invalid trail rule"),
+        None => TrailRule::default(),
+    };
+    let graph = match Graph::load(path, args.iter().any(|a| a == "--dots"), rule) {
+        Ok(graph) => graph,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            process::exit(1);
+        }
+    };
+    println!("Part 1: {}", part1(&graph));
+    println!("Part 2: {}", part2(&graph));
+    if args.iter().any(|a| a == "--scores") {
+        for s in graph.scores() {
+            let (x, y) = s.trailhead;
+            println!("({x},{y}): {} peaks, {} trails", s.peaks, s.trails);
+        }
+    }
+    if let Some(k) = args.iter().position(|a| a == "--trails") {
+        let (x, y) = args[k + 1].split_once(',').expect("Expected x,y");
+        let head = (x.parse().expect("Invalid x"), y.parse().expect("Invalid y"));
+        for trail in graph.trails_from(head) {
+            println!(
+                "{}",
+                trail.iter().map(|(x, y)| format!("({x},{y})")).join(" ")
+            );
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::collections::HashSet;
 
     #[test]
     fn test_part1() {
-        let graph = Graph::new("test.txt");
-        let trails = hiking_trails(&graph);
-        assert_eq!(part1(&trails), 36);
+        let graph = Graph::load("test.txt", false, TrailRule::default()).unwrap();
+        assert_eq!(part1(&graph), 36);
     }
 
     #[test]
     fn test_part2() {
-        let graph = Graph::new("test.txt");
-        let trails = hiking_trails(&graph);
-        assert_eq!(part2(&trails), 81);
+        let graph = Graph::load("test.txt", false, TrailRule::default()).unwrap();
+        assert_eq!(part2(&graph), 81);
+    }
+
+    #[test]
+    fn test_scores() {
+        // test_tiled.txt is the example repeated 2x2, so trails also cross between copies.
+        for path in ["test.txt", "test_tiled.txt"] {
+            let graph = Graph::load(path, false, TrailRule::default()).unwrap();
+            for score in graph.scores() {
+                let trails = graph.trails_from(score.trailhead);
+                assert_eq!(score.trails, trails.len());
+                let peaks: HashSet<GridPoint> = trails.iter().map(|t| *t.last().unwrap()).collect();
+                assert_eq!(score.peaks, peaks.len());
+                assert!(trails.iter().all(|t| t.len() == 10));
+            }
+        }
+        let graph = Graph::load("test.txt", false, TrailRule::default()).unwrap();
+        let scores: Vec<usize> = graph.scores().iter().map(|s| s.peaks).collect();
+        assert_eq!(scores, vec![5, 6, 5, 3, 1, 3, 5, 3, 5]);
+    }
+
+    #[test]
+    fn test_map_errors() {
+        let example = "..90..9\n...1.98\n...2..7\n6543456\n765.987\n876....\n987....";
+        assert_eq!(
+            TopoMap::parse(example, false).err(),
+            Some(MapError::InvalidCell { x: 0, y: 0, c: '.' })
+        );
+        let graph = Graph::from_map(TopoMap::parse(example, true).unwrap(), TrailRule::default());
+        assert_eq!(part1(&graph), 4);
+        assert_eq!(
+            TopoMap::parse("0123\n45x7", true).err(),
+            Some(MapError::InvalidCell { x: 2, y: 1, c: 'x' })
+        );
+        assert_eq!(
+            TopoMap::parse("0123\n456", false).err(),
+            Some(MapError::RaggedRow {
+                y: 1,
+                len: 3,
+                width: 4
+            })
+        );
+        assert!(TopoMap::parse("0123\n4567\n", false).is_ok());
+    }
+
+    #[test]
+    fn test_large_maps() {
+        // Far past the 127 cells an i8 could index, but small enough for every test run.
+        let (width, height) = (600, 200);
+        let graph = Graph::from_map(ramps(width, height), TrailRule::default());
+        assert_eq!(part1(&graph), width / 10 * height);
+        assert_eq!(part2(&graph), width / 10 * height);
+        let last = graph.scores().pop().unwrap();
+        assert_eq!(last.trailhead, (590, 199));
+        assert_eq!(
+            graph.trails_from(last.trailhead)[0].last(),
+            Some(&(599, 199))
+        );
+    }
+
+    // A 0..9 ramp every ten columns, so each trailhead climbs straight right to one peak.
+    fn ramps(width: usize, height: usize) -> TopoMap {
+        let raw = (0..height)
+            .map(|_| {
+                (0..width)
+                    .map(|x| char::from(b'0' + (x % 10) as u8))
+                    .collect::<String>()
+            })
+            .join("\n");
+        TopoMap::parse(&raw, false).unwrap()
+    }
+
+    #[test]
+    fn test_trail_rules() {
+        let rule = |s: &str| s.parse::<TrailRule>().unwrap();
+        assert_eq!(rule(""), TrailRule::default());
+        assert_eq!(rule("step=1,trailhead=0,peak=9"), TrailRule::default());
+        for bad in ["step=0", "peak=10", "trailhead=x", "uphill"] {
+            assert!(bad.parse::<TrailRule>().is_err());
+        }
+
+        // Walking the example downhill finds the same trails, reversed.
+        let graph = Graph::load("test.txt", false, rule("descending,trailhead=9,peak=0")).unwrap();
+        assert_eq!(part1(&graph), 36);
+        assert_eq!(part2(&graph), 81);
+
+        let small = |raw: &str, spec: &str| {
+            let graph = Graph::from_map(TopoMap::parse(raw, false).unwrap(), rule(spec));
+            (part1(&graph), part2(&graph))
+        };
+        assert_eq!(small("02468", ""), (0, 0));
+        assert_eq!(small("02468", "peak=8"), (0, 0));
+        assert_eq!(small("02468", "step=2,peak=8"), (1, 1));
+        assert_eq!(small("01\n12", "peak=2"), (1, 2));
+        assert_eq!(small("01\n12", "diagonal,peak=2"), (1, 2));
+        assert_eq!(small("01\n12", "diagonal,step=2,peak=2"), (1, 3));
+        assert_eq!(small("5", "trailhead=5,peak=5"), (1, 1));
+
+        for spec in [
+            "step=2",
+            "descending,trailhead=9,peak=0",
+            "diagonal",
+            "diagonal,step=3,peak=7",
+        ] {
+            let graph = Graph::load("test.txt", false, rule(spec)).unwrap();
+            for score in graph.scores() {
+                let trails = graph.trails_from(score.trailhead);
+                assert_eq!(score.trails, trails.len());
+                let peaks: HashSet<GridPoint> = trails.iter().map(|t| *t.last().unwrap()).collect();
+                assert_eq!(score.peaks, peaks.len());
+            }
+        }
     }
 }